@@ -1,14 +1,20 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
+use crate::rejection::Rejection;
 use crate::transaction::Amount;
 
-#[derive(Clone, Copy, Serialize, PartialEq, Debug)]
+// Account files written before pending balances and closed accounts were added can still be read.
+#[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Debug)]
 pub struct Account {
     pub client: u16,
     pub available: Amount,
+    #[serde(default)]
+    pub pending: Amount,
     pub held: Amount,
     pub total: Amount,
     pub locked: bool,
+    #[serde(default)]
+    pub closed: bool,
 }
 
 impl Account {
@@ -16,9 +22,28 @@ impl Account {
         Account {
             client,
             available: Amount(0),
+            pending: Amount(0),
             held: Amount(0),
             total: Amount(0),
             locked: false,
+            closed: false,
         }
     }
+
+    /// Returns an error if the account is locked or closed, and so cannot accept funds movements.
+    pub fn check_open(&self) -> Result<(), Rejection> {
+        self.check_not_closed()?;
+        if self.locked {
+            return Err(Rejection::AccountLocked);
+        }
+        Ok(())
+    }
+
+    /// Returns an error if the account has been closed.
+    pub fn check_not_closed(&self) -> Result<(), Rejection> {
+        if self.closed {
+            return Err(Rejection::AccountClosed);
+        }
+        Ok(())
+    }
 }
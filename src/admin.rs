@@ -0,0 +1,17 @@
+use serde::Serialize;
+
+use crate::transaction::TxType;
+
+/// A record of an admin action that was applied to an account, kept so that every manual
+/// intervention can be traced back to the operator responsible for it.
+#[derive(Clone, Serialize, PartialEq, Debug)]
+pub struct AdminAction {
+    #[serde(rename = "type")]
+    pub tx_type: TxType,
+    pub client: u16,
+    #[serde(rename = "tx")]
+    pub id: u32,
+    pub operator: u32,
+    pub reason: String,
+    pub timestamp: Option<u64>,
+}
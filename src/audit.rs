@@ -0,0 +1,179 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::process;
+use std::str::FromStr;
+
+use serde::Serialize;
+use tracing::error;
+
+use crate::account::Account;
+use crate::input::Location;
+use crate::transaction::{Amount, Transaction, TxType};
+
+/// Formats the audit log can be written in.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum AuditFormat {
+    #[default]
+    Csv,
+    Jsonl,
+}
+
+impl FromStr for AuditFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<AuditFormat, String> {
+        match format {
+            "csv" => Ok(AuditFormat::Csv),
+            "jsonl" => Ok(AuditFormat::Jsonl),
+            _ => Err(format!("expected csv or jsonl, found {format}")),
+        }
+    }
+}
+
+/// What became of a row of input.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    Applied,
+    Rejected,
+    // The row couldn't be read as a transaction.
+    Unreadable,
+}
+
+/// A record of a row of input, what became of it, and the client's balances before and after it
+/// was applied. Unreadable rows have no transaction or balances.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct AuditRecord<'a> {
+    pub file: &'a Path,
+    pub line: u64,
+    #[serde(rename = "type")]
+    pub tx_type: Option<TxType>,
+    pub client: Option<u16>,
+    pub tx: Option<u32>,
+    pub amount: Option<Amount>,
+    pub timestamp: Option<u64>,
+    pub outcome: Outcome,
+    pub reason: Option<String>,
+    pub available_before: Option<Amount>,
+    pub pending_before: Option<Amount>,
+    pub held_before: Option<Amount>,
+    pub total_before: Option<Amount>,
+    pub locked_before: Option<bool>,
+    pub available_after: Option<Amount>,
+    pub pending_after: Option<Amount>,
+    pub held_after: Option<Amount>,
+    pub total_after: Option<Amount>,
+    pub locked_after: Option<bool>,
+}
+
+impl AuditRecord<'_> {
+    /// Creates the record of a transaction that the engine applied or rejected.
+    pub fn transaction<'a>(
+        location: &'a Location,
+        transaction: &Transaction,
+        outcome: Outcome,
+        reason: Option<String>,
+        before: Account,
+        after: Account,
+    ) -> AuditRecord<'a> {
+        AuditRecord {
+            file: &location.path,
+            line: location.line,
+            tx_type: Some(transaction.tx_type),
+            client: Some(transaction.client),
+            tx: Some(transaction.id),
+            amount: transaction.amount,
+            timestamp: transaction.timestamp,
+            outcome,
+            reason,
+            available_before: Some(before.available),
+            pending_before: Some(before.pending),
+            held_before: Some(before.held),
+            total_before: Some(before.total),
+            locked_before: Some(before.locked),
+            available_after: Some(after.available),
+            pending_after: Some(after.pending),
+            held_after: Some(after.held),
+            total_after: Some(after.total),
+            locked_after: Some(after.locked),
+        }
+    }
+
+    /// Creates the record of a row that couldn't be read as a transaction.
+    pub fn unreadable(location: &Location, reason: String) -> AuditRecord<'_> {
+        AuditRecord {
+            file: &location.path,
+            line: location.line,
+            tx_type: None,
+            client: None,
+            tx: None,
+            amount: None,
+            timestamp: None,
+            outcome: Outcome::Unreadable,
+            reason: Some(reason),
+            available_before: None,
+            pending_before: None,
+            held_before: None,
+            total_before: None,
+            locked_before: None,
+            available_after: None,
+            pending_after: None,
+            held_after: None,
+            total_after: None,
+            locked_after: None,
+        }
+    }
+}
+
+/// An audit log file, which records are appended to as rows are processed.
+pub enum AuditLog {
+    Csv(Box<csv::Writer<File>>),
+    Jsonl(BufWriter<File>),
+}
+
+impl AuditLog {
+    /// Creates the audit log file at the provided path, exiting if this fails.
+    pub fn create(path: &Path, format: AuditFormat) -> AuditLog {
+        let file = File::create(path)
+            .or_else::<std::io::Error, _>(|e| {
+                error!("Failed to create audit log: {e}");
+                process::exit(1)
+            })
+            .unwrap();
+        match format {
+            AuditFormat::Csv => AuditLog::Csv(Box::new(csv::Writer::from_writer(file))),
+            AuditFormat::Jsonl => AuditLog::Jsonl(BufWriter::new(file)),
+        }
+    }
+
+    /// Appends a record to the audit log, exiting if this fails.
+    pub fn write(&mut self, record: &AuditRecord) {
+        let result = match self {
+            AuditLog::Csv(wtr) => wtr.serialize(record).map_err(|e| e.to_string()),
+            AuditLog::Jsonl(wtr) => serde_json::to_writer(&mut *wtr, record)
+                .and_then(|()| writeln!(wtr).map_err(serde_json::Error::io))
+                .map_err(|e| e.to_string()),
+        };
+        result
+            .or_else::<String, _>(|e| {
+                error!("Failed to write to audit log: {e}");
+                process::exit(1)
+            })
+            .unwrap();
+    }
+
+    /// Flushes the records written so far to the file, exiting if this fails.
+    pub fn flush(&mut self) {
+        let result = match self {
+            AuditLog::Csv(wtr) => wtr.flush(),
+            AuditLog::Jsonl(wtr) => wtr.flush(),
+        };
+        result
+            .or_else::<std::io::Error, _>(|e| {
+                error!("Failed to flush audit log: {e}");
+                process::exit(1)
+            })
+            .unwrap();
+    }
+}
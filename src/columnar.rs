@@ -0,0 +1,366 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::process;
+use std::sync::Arc;
+
+use arrow::array::{
+    Array, ArrayRef, AsArray, BooleanArray, Decimal128Array, RecordBatch, UInt16Array,
+};
+use arrow::compute::cast;
+use arrow::datatypes::{
+    ArrowPrimitiveType, DataType, Field, Schema, UInt16Type, UInt32Type, UInt64Type,
+};
+use arrow::error::ArrowError;
+use arrow::ipc::reader::FileReader;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::arrow::ArrowWriter;
+use parquet::errors::ParquetError;
+use serde::de::IntoDeserializer;
+use serde::Deserialize;
+use tracing::error;
+
+use crate::account::Account;
+use crate::transaction::{Amount, Transaction, TxType};
+
+/// Columnar file formats that transactions can be read from in batches.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColumnarFormat {
+    Parquet,
+    // The Arrow IPC file format.
+    ArrowIpc,
+}
+
+impl fmt::Display for ColumnarFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ColumnarFormat::Parquet => write!(f, "parquet"),
+            ColumnarFormat::ArrowIpc => write!(f, "arrow"),
+        }
+    }
+}
+
+type Batches = Box<dyn Iterator<Item = Result<RecordBatch, ArrowError>>>;
+
+/// Reads transactions from a columnar file, decoding a whole batch of rows at a time.
+pub struct BatchReader {
+    format: ColumnarFormat,
+    batches: Batches,
+    // Rows decoded from the current batch that have yet to be read.
+    rows: VecDeque<Result<Transaction, String>>,
+    // How many rows have been read so far.
+    read: u64,
+}
+
+impl BatchReader {
+    /// Opens the file at the provided path, exiting if it cannot be read.
+    pub fn open(path: &Path, format: ColumnarFormat) -> BatchReader {
+        BatchReader {
+            format,
+            batches: open_batches(path, format),
+            rows: VecDeque::new(),
+            read: 0,
+        }
+    }
+
+    /// Reads the next row, along with its position in the file starting from 1, returning `None`
+    /// at the end of the input.
+    pub fn next_row(&mut self) -> Option<(u64, Result<Transaction, String>)> {
+        loop {
+            if let Some(row) = self.rows.pop_front() {
+                self.read += 1;
+                return Some((self.read, row));
+            }
+            let batch = self
+                .batches
+                .next()?
+                .or_else::<ArrowError, _>(|e| {
+                    error!("Failed to read batch from {}: {e}", self.format);
+                    process::exit(1)
+                })
+                .unwrap();
+            self.rows = decode_batch(&batch).into();
+        }
+    }
+}
+
+/// Opens the file at the provided path as an iterator of record batches, exiting if this fails.
+fn open_batches(path: &Path, format: ColumnarFormat) -> Batches {
+    let file = File::open(path)
+        .or_else::<std::io::Error, _>(|e| {
+            error!("Failed to read {format}: {e}");
+            process::exit(1)
+        })
+        .unwrap();
+    match format {
+        ColumnarFormat::Parquet => Box::new(
+            ParquetRecordBatchReaderBuilder::try_new(file)
+                .and_then(ParquetRecordBatchReaderBuilder::build)
+                .or_else::<ParquetError, _>(|e| {
+                    error!("Failed to read parquet: {e}");
+                    process::exit(1)
+                })
+                .unwrap(),
+        ),
+        ColumnarFormat::ArrowIpc => Box::new(
+            FileReader::try_new(file, None)
+                .or_else::<ArrowError, _>(|e| {
+                    error!("Failed to read arrow: {e}");
+                    process::exit(1)
+                })
+                .unwrap(),
+        ),
+    }
+}
+
+/// Decodes every row of a batch into a transaction. Columns are found by the same names as csv
+/// headers, and are cast to the types they're expected to hold; amounts are read via their decimal
+/// representation so that decimal, float and string columns are all read exactly.
+fn decode_batch(batch: &RecordBatch) -> Vec<Result<Transaction, String>> {
+    let types = required_column(batch, "type", &DataType::Utf8);
+    let clients = required_column(batch, "client", &DataType::UInt16);
+    let ids = required_column(batch, "tx", &DataType::UInt32);
+    let amounts = optional_column(batch, "amount", &DataType::Utf8);
+    let operators = optional_column(batch, "operator", &DataType::UInt32);
+    let reasons = optional_column(batch, "reason", &DataType::Utf8);
+    let timestamps = optional_column(batch, "timestamp", &DataType::UInt64);
+
+    (0..batch.num_rows())
+        .map(|row| {
+            let tx_type = string(&types, row).ok_or("type is missing")?;
+            let tx_type = TxType::deserialize(tx_type.into_deserializer())
+                .map_err(|e: serde::de::value::Error| e.to_string())?;
+            Ok(Transaction {
+                tx_type,
+                client: primitive::<UInt16Type>(&clients, row)
+                    .ok_or("client is missing or out of range")?,
+                id: primitive::<UInt32Type>(&ids, row).ok_or("tx is missing or out of range")?,
+                amount: amounts
+                    .as_ref()
+                    .and_then(|amounts| string(amounts, row))
+                    .map(str::parse::<Amount>)
+                    .transpose()?,
+                operator: operators
+                    .as_ref()
+                    .and_then(|operators| primitive::<UInt32Type>(operators, row)),
+                reason: reasons
+                    .as_ref()
+                    .and_then(|reasons| string(reasons, row))
+                    .map(str::to_string),
+                timestamp: timestamps
+                    .as_ref()
+                    .and_then(|timestamps| primitive::<UInt64Type>(timestamps, row)),
+            })
+        })
+        .collect()
+}
+
+/// Finds the named column and casts it to the given type, exiting if it is missing.
+fn required_column(batch: &RecordBatch, name: &str, data_type: &DataType) -> ArrayRef {
+    optional_column(batch, name, data_type)
+        .or_else(|| {
+            error!("Transaction batch has no {name} column");
+            process::exit(1)
+        })
+        .unwrap()
+}
+
+/// Finds the named column, if there is one, and casts it to the given type. Values that can't be
+/// represented in that type become null.
+fn optional_column(batch: &RecordBatch, name: &str, data_type: &DataType) -> Option<ArrayRef> {
+    let column = batch.column_by_name(name)?;
+    Some(
+        cast(column, data_type)
+            .or_else::<ArrowError, _>(|e| {
+                error!("Failed to read the {name} column: {e}");
+                process::exit(1)
+            })
+            .unwrap(),
+    )
+}
+
+fn primitive<T: ArrowPrimitiveType>(column: &ArrayRef, row: usize) -> Option<T::Native> {
+    let column = column.as_primitive::<T>();
+    column.is_valid(row).then(|| column.value(row))
+}
+
+fn string(column: &ArrayRef, row: usize) -> Option<&str> {
+    let column = column.as_string::<i32>();
+    column.is_valid(row).then(|| column.value(row))
+}
+
+/// Reads account details from a parquet file, such as one written by `write_accounts`. Amounts
+/// are read via their decimal representation, like transaction amounts.
+pub fn read_accounts(path: &Path) -> Result<Vec<Account>, String> {
+    let mut accounts = Vec::new();
+    for batch in open_batches(path, ColumnarFormat::Parquet) {
+        let batch = batch.map_err(|e| e.to_string())?;
+        let column = |name, data_type| {
+            optional_column(&batch, name, data_type).ok_or(format!("{name} column is missing"))
+        };
+        let clients = column("client", &DataType::UInt16)?;
+        let available = column("available", &DataType::Utf8)?;
+        let pending = column("pending", &DataType::Utf8)?;
+        let held = column("held", &DataType::Utf8)?;
+        let total = column("total", &DataType::Utf8)?;
+        let locked = column("locked", &DataType::Boolean)?;
+        let closed = column("closed", &DataType::Boolean)?;
+
+        let amount = |amounts: &ArrayRef, row| {
+            string(amounts, row)
+                .ok_or("amount is missing")?
+                .parse::<Amount>()
+        };
+        let flag = |flags: &ArrayRef, row| {
+            let flags = flags.as_boolean();
+            flags.is_valid(row).then(|| flags.value(row))
+        };
+        for row in 0..batch.num_rows() {
+            accounts.push(Account {
+                client: primitive::<UInt16Type>(&clients, row)
+                    .ok_or("client is missing or out of range")?,
+                available: amount(&available, row)?,
+                pending: amount(&pending, row)?,
+                held: amount(&held, row)?,
+                total: amount(&total, row)?,
+                locked: flag(&locked, row).ok_or("locked is missing")?,
+                closed: flag(&closed, row).ok_or("closed is missing")?,
+            });
+        }
+    }
+    Ok(accounts)
+}
+
+/// Writes account details as a single parquet row group. Amounts are written as decimals with 4
+/// decimal places.
+pub fn write_accounts<W: Write + Send>(
+    accounts: &[&Account],
+    writer: W,
+) -> Result<(), ParquetError> {
+    let amount_type = DataType::Decimal128(20, 4);
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("client", DataType::UInt16, false),
+        Field::new("available", amount_type.clone(), false),
+        Field::new("pending", amount_type.clone(), false),
+        Field::new("held", amount_type.clone(), false),
+        Field::new("total", amount_type, false),
+        Field::new("locked", DataType::Boolean, false),
+        Field::new("closed", DataType::Boolean, false),
+    ]));
+    let amounts = |amount: fn(&Account) -> Amount| -> Result<ArrayRef, ArrowError> {
+        let amounts = accounts.iter().map(|account| i128::from(amount(account).0));
+        Ok(Arc::new(
+            Decimal128Array::from_iter_values(amounts).with_precision_and_scale(20, 4)?,
+        ))
+    };
+    let batch = RecordBatch::try_new(
+        schema.clone(),
+        vec![
+            Arc::new(UInt16Array::from_iter_values(
+                accounts.iter().map(|account| account.client),
+            )),
+            amounts(|account| account.available)?,
+            amounts(|account| account.pending)?,
+            amounts(|account| account.held)?,
+            amounts(|account| account.total)?,
+            Arc::new(BooleanArray::from(
+                accounts
+                    .iter()
+                    .map(|account| account.locked)
+                    .collect::<Vec<_>>(),
+            )),
+            Arc::new(BooleanArray::from(
+                accounts
+                    .iter()
+                    .map(|account| account.closed)
+                    .collect::<Vec<_>>(),
+            )),
+        ],
+    )?;
+
+    let mut writer = ArrowWriter::try_new(writer, schema, None)?;
+    writer.write(&batch)?;
+    writer.close()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::fs::File;
+    use std::sync::Arc;
+
+    use arrow::array::{
+        ArrayRef, Float64Array, RecordBatch, StringArray, UInt16Array, UInt32Array,
+    };
+    use arrow::datatypes::Decimal128Type;
+    use parquet::arrow::ArrowWriter;
+
+    use crate::account::Account;
+    use crate::columnar::{
+        open_batches, read_accounts, write_accounts, BatchReader, ColumnarFormat,
+    };
+    use crate::transaction::{Amount, TxType};
+
+    #[test]
+    fn read_parquet_transactions() {
+        let path = env::temp_dir().join("payments-engine-transactions.parquet");
+        let columns: Vec<(&str, ArrayRef)> = vec![
+            (
+                "type",
+                Arc::new(StringArray::from(vec!["deposit", "dispute", "bogus"])),
+            ),
+            ("client", Arc::new(UInt16Array::from(vec![1, 1, 1]))),
+            ("tx", Arc::new(UInt32Array::from(vec![1, 1, 2]))),
+            (
+                "amount",
+                Arc::new(Float64Array::from(vec![Some(0.3), None, None])),
+            ),
+        ];
+        let batch = RecordBatch::try_from_iter(columns).unwrap();
+        let mut writer =
+            ArrowWriter::try_new(File::create(&path).unwrap(), batch.schema(), None).unwrap();
+        writer.write(&batch).unwrap();
+        writer.close().unwrap();
+
+        let mut reader = BatchReader::open(&path, ColumnarFormat::Parquet);
+        let (row, deposit) = reader.next_row().unwrap();
+        assert_eq!(row, 1);
+        let deposit = deposit.unwrap();
+        assert_eq!(deposit.tx_type, TxType::Deposit);
+        assert_eq!(deposit.amount, Some(Amount(3_000)));
+        let dispute = reader.next_row().unwrap().1.unwrap();
+        assert_eq!(dispute.tx_type, TxType::Dispute);
+        assert_eq!(dispute.amount, None);
+        assert!(reader.next_row().unwrap().1.is_err());
+        assert!(reader.next_row().is_none());
+    }
+
+    #[test]
+    fn write_parquet_accounts() {
+        let path = env::temp_dir().join("payments-engine-accounts.parquet");
+        let account = Account {
+            available: Amount(15_000),
+            total: Amount(15_000),
+            ..Account::new(7)
+        };
+        write_accounts(&[&account], File::create(&path).unwrap()).unwrap();
+
+        let batch = open_batches(&path, ColumnarFormat::Parquet)
+            .next()
+            .unwrap()
+            .unwrap();
+        assert_eq!(batch.num_rows(), 1);
+        let available = batch
+            .column_by_name("available")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<arrow::array::PrimitiveArray<Decimal128Type>>()
+            .unwrap();
+        assert_eq!(available.value_as_string(0), "1.5000");
+
+        assert_eq!(read_accounts(&path), Ok(vec![account]));
+    }
+}
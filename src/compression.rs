@@ -0,0 +1,75 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+
+use flate2::bufread::MultiGzDecoder;
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+/// Compression formats that input files are transparently decompressed from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    /// Detects compression from the file's extension, or failing that from its first bytes.
+    fn detect(path: &Path, start: &[u8]) -> Compression {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("gz") => Compression::Gzip,
+            Some("zst") => Compression::Zstd,
+            _ if start.starts_with(GZIP_MAGIC) => Compression::Gzip,
+            _ if start.starts_with(ZSTD_MAGIC) => Compression::Zstd,
+            _ => Compression::None,
+        }
+    }
+}
+
+/// Opens the file at the provided path for reading, decompressing it if it is compressed.
+pub fn open(path: &Path) -> io::Result<Box<dyn Read>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    Ok(match Compression::detect(path, reader.fill_buf()?) {
+        Compression::None => Box::new(reader),
+        // Concatenated gzip members are read as one stream, as gunzip does.
+        Compression::Gzip => Box::new(MultiGzDecoder::new(reader)),
+        Compression::Zstd => Box::new(zstd::Decoder::with_buffer(reader)?),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use crate::compression::Compression;
+
+    #[test]
+    fn detect_compression() {
+        let plain = b"type,client,tx,amount";
+        let gzip = [0x1f, 0x8b, 0x08, 0x00];
+        let zstd = [0x28, 0xb5, 0x2f, 0xfd, 0x00];
+
+        assert_eq!(
+            Compression::detect(Path::new("a.csv"), plain),
+            Compression::None
+        );
+        assert_eq!(
+            Compression::detect(Path::new("a.csv.gz"), plain),
+            Compression::Gzip
+        );
+        assert_eq!(
+            Compression::detect(Path::new("a.csv.zst"), plain),
+            Compression::Zstd
+        );
+        assert_eq!(
+            Compression::detect(Path::new("a.csv"), &gzip),
+            Compression::Gzip
+        );
+        assert_eq!(
+            Compression::detect(Path::new("a"), &zstd),
+            Compression::Zstd
+        );
+    }
+}
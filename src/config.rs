@@ -0,0 +1,524 @@
+use std::fmt::Display;
+use std::fs;
+use std::iter;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use clap::error::ErrorKind;
+use clap::{Args, CommandFactory, Parser, Subcommand};
+use serde::de::{self, Deserializer};
+use serde::Deserialize;
+
+use crate::audit::AuditFormat;
+use crate::engine::Engine;
+use crate::expiry::{DisputeWindows, ExpiryAction};
+use crate::hold::HoldPeriods;
+use crate::input::InputFormat;
+use crate::logging::LogFormat;
+use crate::output::OutputFormat;
+use crate::policy::Policy;
+use crate::replay::ReplayPoint;
+
+/// What to do with the transactions.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    // Write the final state of every account.
+    Process,
+    // Write the statement of a single client, or of every client.
+    Statement {
+        client: Option<u16>,
+    },
+    // Write the state of every account, or of a single client's account, at the replay point.
+    Replay {
+        client: Option<u16>,
+    },
+    // Write each of a client's rows, what became of it and the balances after it.
+    Explain {
+        client: u16,
+    },
+    // Write a summary instead of any results, failing if too many rows are unreadable or rejected.
+    Validate {
+        max_rejection_rate: f64,
+    },
+    // Apply transactions sent over HTTP to the address, rather than reading them from files.
+    Serve {
+        listen: String,
+    },
+    // Write the differences between two account files written in the given format, rather than
+    // reading transactions.
+    Diff {
+        old: PathBuf,
+        new: PathBuf,
+        accounts_format: OutputFormat,
+    },
+}
+
+/// Options controlling how transactions are read and processed, and where results are written.
+#[derive(Default, Debug)]
+pub struct Config {
+    // The format transactions are read in.
+    pub input_format: InputFormat,
+    // The format accounts and statements are written in.
+    pub output_format: OutputFormat,
+    // Where to record applied admin actions, if anywhere.
+    pub admin_log: Option<PathBuf>,
+    // Where to record the ledger's journal entries, if anywhere.
+    pub journal: Option<PathBuf>,
+    // Where to record the flags raised on applied transactions, if anywhere.
+    pub alerts: Option<PathBuf>,
+    // Where to write the control totals reconciling the run, if anywhere.
+    pub reconciliation: Option<PathBuf>,
+    // Where to record what became of each row of input, if anywhere, and in what format.
+    pub audit_log: Option<PathBuf>,
+    pub audit_format: AuditFormat,
+    // Which logs to write to stderr, and in what format.
+    pub log_level: String,
+    pub log_format: LogFormat,
+    // If set, transactions are buffered and sorted by timestamp within this many seconds.
+    pub reorder_window: Option<u64>,
+    // How long deposits are held before they may be withdrawn.
+    pub hold_periods: HoldPeriods,
+    // How long deposits may be disputed for, and how long disputes may stay open.
+    pub dispute_windows: DisputeWindows,
+    // If set, processing stops at this point in the input.
+    pub until: Option<ReplayPoint>,
+    // If set, each of this client's rows is recorded in the summary as it is processed.
+    pub explain: Option<u16>,
+    // If set, the engine keeps the history of applied transactions that statements are written
+    // from.
+    pub statement: bool,
+    // If set, the input must follow the schema exactly, and processing stops at the first row
+    // that doesn't.
+    pub strict: bool,
+    // Business rules, such as which transactions may be disputed.
+    pub policy: Policy,
+}
+
+/// Processes transactions into client account balances.
+#[derive(Parser, Debug)]
+#[command(
+    name = "payments-engine",
+    version,
+    about,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<CliCommand>,
+    // Without a command, the transactions are processed.
+    #[command(flatten)]
+    inputs: Inputs,
+}
+
+#[derive(Subcommand, Debug)]
+enum CliCommand {
+    /// Write the final state of every account (the default)
+    Process(Inputs),
+    /// Check the transactions and write a summary instead of accounts
+    Validate {
+        /// Fail if a greater fraction of rows than this are unreadable or rejected
+        #[arg(long, env = "PAYMENTS_MAX_REJECTION_RATE", default_value_t = 0.0)]
+        max_rejection_rate: f64,
+        #[command(flatten)]
+        inputs: Inputs,
+    },
+    /// Write each applied transaction along with the running balances
+    Statement {
+        /// Only include this client's transactions
+        #[arg(long)]
+        client: Option<u16>,
+        #[command(flatten)]
+        inputs: Inputs,
+    },
+    /// Write accounts as they were at a point in the input, replaying it from the first row
+    Replay {
+        /// Only include this client's account
+        #[arg(long)]
+        client: Option<u16>,
+        #[command(flatten)]
+        inputs: Inputs,
+    },
+    /// Write each of a client's rows, whether it was applied or why not, and the balances after it
+    Explain {
+        /// The client to explain
+        #[arg(long)]
+        client: u16,
+        #[command(flatten)]
+        inputs: Inputs,
+    },
+    /// Apply transactions posted over HTTP, and serve the resulting accounts
+    Serve {
+        /// Address to listen on
+        #[arg(long, env = "PAYMENTS_LISTEN", default_value = "127.0.0.1:8080")]
+        listen: String,
+        #[command(flatten)]
+        options: Options,
+    },
+    /// Write the differences between two account files, such as those from two engine versions
+    Diff {
+        /// Account file to compare against
+        old: PathBuf,
+        /// Account file to compare
+        new: PathBuf,
+        /// Format both account files are in: csv, json or jsonl, or with the parquet feature,
+        /// parquet [default: csv]
+        #[arg(long, env = "PAYMENTS_ACCOUNTS_FORMAT")]
+        accounts_format: Option<OutputFormat>,
+        #[command(flatten)]
+        options: Options,
+    },
+}
+
+#[derive(Args, Debug)]
+struct Inputs {
+    /// Transaction files, directories or glob patterns, read in order as a single stream
+    #[arg(required = true)]
+    paths: Vec<String>,
+    /// Stop processing at this point in the input: row:N, tx:ID or time:T (required by replay)
+    #[arg(long)]
+    until: Option<ReplayPoint>,
+    #[command(flatten)]
+    options: Options,
+}
+
+/// Options that may be given on the command line, in the environment or in a TOML config file, in
+/// order of precedence. Config file keys are named like the command line options.
+#[derive(Args, Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct Options {
+    /// Read options from this TOML file
+    #[arg(long, env = "PAYMENTS_CONFIG")]
+    #[serde(skip)]
+    config: Option<PathBuf>,
+    /// Format to read transactions in: csv or jsonl, or with the parquet feature, parquet or arrow
+    #[arg(long, env = "PAYMENTS_INPUT_FORMAT")]
+    #[serde(deserialize_with = "parsed")]
+    input_format: Option<InputFormat>,
+    /// Format to write results in: csv, json or jsonl, or with the parquet feature, parquet
+    #[arg(long, env = "PAYMENTS_OUTPUT_FORMAT")]
+    #[serde(deserialize_with = "parsed")]
+    output_format: Option<OutputFormat>,
+    /// Write applied admin actions to this csv file
+    #[arg(long, env = "PAYMENTS_ADMIN_LOG")]
+    admin_log: Option<PathBuf>,
+    /// Write the ledger's journal entries to this csv file
+    #[arg(long, env = "PAYMENTS_JOURNAL")]
+    journal: Option<PathBuf>,
+    /// Write the flags raised on applied transactions to this csv file
+    #[arg(long, env = "PAYMENTS_ALERTS")]
+    alerts: Option<PathBuf>,
+    /// Write control totals reconciling funds moved with closing balances to this csv file
+    #[arg(long, env = "PAYMENTS_RECONCILIATION")]
+    reconciliation: Option<PathBuf>,
+    /// Write a record of each row of input, its outcome and the balances before and after it to
+    /// this file
+    #[arg(long, env = "PAYMENTS_AUDIT_LOG")]
+    audit_log: Option<PathBuf>,
+    /// Format to write the audit log in: csv or jsonl
+    #[arg(long, env = "PAYMENTS_AUDIT_FORMAT")]
+    #[serde(deserialize_with = "parsed")]
+    audit_format: Option<AuditFormat>,
+    /// Which logs to write: a level such as warn or debug, or directives in the `RUST_LOG` style
+    /// [default: info]
+    #[arg(long, env = "PAYMENTS_LOG_LEVEL")]
+    log_level: Option<String>,
+    /// Format to write logs to stderr in: text or json
+    #[arg(long, env = "PAYMENTS_LOG_FORMAT")]
+    #[serde(deserialize_with = "parsed")]
+    log_format: Option<LogFormat>,
+    /// Sort transactions by timestamp within this many seconds
+    #[arg(long, env = "PAYMENTS_REORDER_WINDOW")]
+    reorder_window: Option<u64>,
+    /// Hold deposits for this many seconds before they may be withdrawn
+    #[arg(long, env = "PAYMENTS_HOLD_PERIOD")]
+    hold_period: Option<u64>,
+    /// Hold a client's deposits for a period of their own, given as CLIENT:SECONDS
+    #[arg(long, env = "PAYMENTS_CLIENT_HOLD_PERIOD", value_delimiter = ',')]
+    #[serde(deserialize_with = "parsed_list")]
+    client_hold_period: Vec<ClientHoldPeriod>,
+    /// Reject disputes of deposits older than this many seconds
+    #[arg(long, env = "PAYMENTS_DISPUTE_MAX_AGE")]
+    dispute_max_age: Option<u64>,
+    /// Settle disputes left open for this many seconds
+    #[arg(long, env = "PAYMENTS_DISPUTE_DEADLINE")]
+    dispute_deadline: Option<u64>,
+    /// How to settle disputes past their deadline: resolve or chargeback
+    #[arg(long, env = "PAYMENTS_DISPUTE_EXPIRY")]
+    #[serde(deserialize_with = "parsed")]
+    dispute_expiry: Option<ExpiryAction>,
+    /// Require input to follow the schema exactly, stopping at the first row that doesn't
+    #[arg(long, env = "PAYMENTS_STRICT")]
+    strict: bool,
+    /// Read business rules, such as which transactions may be disputed, from this TOML file
+    #[arg(long, env = "PAYMENTS_POLICY")]
+    policy: Option<PathBuf>,
+}
+
+/// A hold period for a single client, given as `CLIENT:SECONDS`.
+#[derive(Debug, Clone, Copy)]
+struct ClientHoldPeriod(u16, u64);
+
+impl FromStr for ClientHoldPeriod {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<ClientHoldPeriod, String> {
+        value
+            .split_once(':')
+            .and_then(|(client, period)| {
+                Some(ClientHoldPeriod(client.parse().ok()?, period.parse().ok()?))
+            })
+            .ok_or_else(|| format!("expected CLIENT:SECONDS, found {value}"))
+    }
+}
+
+impl Config {
+    /// Parses command line arguments (excluding the program name) into the command, the
+    /// transactions paths and the config. Options not given on the command line are taken from
+    /// the environment, then from the config file if there is one. Directories and glob patterns
+    /// are expanded into the files they contain or match.
+    pub fn from_args<I: IntoIterator<Item = String>>(
+        args: I,
+    ) -> Result<(Command, Vec<PathBuf>, Config), clap::Error> {
+        let cli = Cli::try_parse_from(iter::once("payments-engine".to_string()).chain(args))?;
+        let (command, inputs) = match cli.command.unwrap_or(CliCommand::Process(cli.inputs)) {
+            CliCommand::Process(inputs) => (Command::Process, inputs),
+            CliCommand::Validate {
+                max_rejection_rate,
+                inputs,
+            } => (Command::Validate { max_rejection_rate }, inputs),
+            CliCommand::Statement { client, inputs } => (Command::Statement { client }, inputs),
+            CliCommand::Replay { client, inputs } => {
+                if inputs.until.is_none() {
+                    return Err(error(
+                        ErrorKind::MissingRequiredArgument,
+                        "The replay command requires a replay point, given by --until",
+                    ));
+                }
+                (Command::Replay { client }, inputs)
+            }
+            CliCommand::Explain { client, inputs } => (Command::Explain { client }, inputs),
+            CliCommand::Serve { listen, options } => {
+                let config = options.load()?.into_config()?;
+                return Ok((Command::Serve { listen }, Vec::new(), config));
+            }
+            CliCommand::Diff {
+                old,
+                new,
+                accounts_format,
+                options,
+            } => {
+                let config = options.load()?.into_config()?;
+                let command = Command::Diff {
+                    old,
+                    new,
+                    accounts_format: accounts_format.unwrap_or_default(),
+                };
+                config.check_output_format(&command)?;
+                return Ok((command, Vec::new(), config));
+            }
+        };
+        Config::from_inputs(command, inputs)
+    }
+
+    fn from_inputs(
+        command: Command,
+        inputs: Inputs,
+    ) -> Result<(Command, Vec<PathBuf>, Config), clap::Error> {
+        let mut paths = Vec::new();
+        for path in &inputs.paths {
+            paths.extend(expand_path(path).map_err(|e| error(ErrorKind::ValueValidation, e))?);
+        }
+        let mut config = inputs.options.load()?.into_config()?;
+        config.until = inputs.until;
+        match command {
+            Command::Explain { client } => config.explain = Some(client),
+            Command::Statement { .. } => config.statement = true,
+            _ => {}
+        }
+        config.check_output_format(&command)?;
+        Ok((command, paths, config))
+    }
+
+    /// Fails if the command writes something other than account details in a format that only
+    /// supports account details.
+    fn check_output_format(&self, command: &Command) -> Result<(), clap::Error> {
+        if matches!(
+            command,
+            Command::Statement { .. } | Command::Explain { .. } | Command::Diff { .. }
+        ) && self.output_format.accounts_only()
+        {
+            return Err(error(
+                ErrorKind::ArgumentConflict,
+                "Parquet output is only supported for account details",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Creates an engine applying the rules given by the config.
+    pub fn new_engine(&self) -> Engine {
+        let mut engine = Engine::new()
+            .with_hold_periods(self.hold_periods.clone())
+            .with_dispute_windows(self.dispute_windows.clone())
+            .with_policy(self.policy.clone());
+        // The journal and history are only kept if they are to be written out.
+        if self.journal.is_some() {
+            engine = engine.with_journal();
+        }
+        if self.statement {
+            engine = engine.with_history();
+        }
+        self.policy
+            .rules
+            .rules()
+            .into_iter()
+            .chain(self.policy.signals.rules())
+            .fold(engine, Engine::with_rule)
+    }
+}
+
+impl Options {
+    /// Fills in options that weren't given on the command line or in the environment from the
+    /// config file, if there is one.
+    fn load(self) -> Result<Options, clap::Error> {
+        let Some(path) = &self.config else {
+            return Ok(self);
+        };
+        let file: Options = fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|contents| toml::from_str(&contents).map_err(|e| e.to_string()))
+            .map_err(|e| {
+                error(
+                    ErrorKind::Io,
+                    format!("Failed to read config file {}: {e}", path.display()),
+                )
+            })?;
+
+        Ok(Options {
+            config: self.config,
+            input_format: self.input_format.or(file.input_format),
+            output_format: self.output_format.or(file.output_format),
+            admin_log: self.admin_log.or(file.admin_log),
+            journal: self.journal.or(file.journal),
+            alerts: self.alerts.or(file.alerts),
+            reconciliation: self.reconciliation.or(file.reconciliation),
+            audit_log: self.audit_log.or(file.audit_log),
+            audit_format: self.audit_format.or(file.audit_format),
+            log_level: self.log_level.or(file.log_level),
+            log_format: self.log_format.or(file.log_format),
+            reorder_window: self.reorder_window.or(file.reorder_window),
+            hold_period: self.hold_period.or(file.hold_period),
+            client_hold_period: if self.client_hold_period.is_empty() {
+                file.client_hold_period
+            } else {
+                self.client_hold_period
+            },
+            dispute_max_age: self.dispute_max_age.or(file.dispute_max_age),
+            dispute_deadline: self.dispute_deadline.or(file.dispute_deadline),
+            dispute_expiry: self.dispute_expiry.or(file.dispute_expiry),
+            strict: self.strict || file.strict,
+            policy: self.policy.or(file.policy),
+        })
+    }
+
+    fn into_config(self) -> Result<Config, clap::Error> {
+        let policy = match &self.policy {
+            Some(path) => Policy::load(path).map_err(|e| error(ErrorKind::Io, e))?,
+            None => Policy::default(),
+        };
+        Ok(Config {
+            input_format: self.input_format.unwrap_or_default(),
+            output_format: self.output_format.unwrap_or_default(),
+            admin_log: self.admin_log,
+            journal: self.journal,
+            alerts: self.alerts,
+            reconciliation: self.reconciliation,
+            audit_log: self.audit_log,
+            audit_format: self.audit_format.unwrap_or_default(),
+            log_level: self.log_level.unwrap_or_else(|| "info".to_string()),
+            log_format: self.log_format.unwrap_or_default(),
+            reorder_window: self.reorder_window,
+            hold_periods: HoldPeriods {
+                default: self.hold_period,
+                clients: self
+                    .client_hold_period
+                    .into_iter()
+                    .map(|ClientHoldPeriod(client, period)| (client, period))
+                    .collect(),
+            },
+            dispute_windows: DisputeWindows {
+                max_deposit_age: self.dispute_max_age,
+                deadline: self.dispute_deadline,
+                on_expiry: self.dispute_expiry.unwrap_or_default(),
+            },
+            until: None,
+            explain: None,
+            statement: false,
+            strict: self.strict,
+            policy,
+        })
+    }
+}
+
+/// Expands a path argument into the files it refers to. A directory gives the files directly inside
+/// it and a glob pattern gives the paths matching it, both in lexicographic order, so that files
+/// named by date or hour are read in time order.
+fn expand_path(arg: &str) -> Result<Vec<PathBuf>, String> {
+    let path = PathBuf::from(arg);
+    let files = if path.is_dir() {
+        let mut files = fs::read_dir(&path)
+            .and_then(|entries| {
+                entries
+                    .map(|entry| entry.map(|entry| entry.path()))
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .map_err(|e| format!("Failed to read directory {arg}: {e}"))?;
+        files.retain(|file| file.is_file());
+        files.sort();
+        files
+    } else if arg.contains(['*', '?', '[']) {
+        glob::glob(arg)
+            .map_err(|e| format!("Invalid glob pattern {arg}: {e}"))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("Failed to read {}: {}", e.path().display(), e.error()))?
+    } else {
+        return Ok(vec![path]);
+    };
+    // An empty directory or a pattern matching nothing is most likely a mistake, so it isn't
+    // treated as empty input.
+    if files.is_empty() {
+        return Err(format!("No files match {arg}"));
+    }
+    Ok(files)
+}
+
+/// Creates an error that is reported like clap's own, along with the usage.
+fn error<M: Display>(kind: ErrorKind, message: M) -> clap::Error {
+    Cli::command().error(kind, message)
+}
+
+/// Deserializes an optional config file value from a string, using its `FromStr` implementation.
+fn parsed<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: Display,
+{
+    Option::<String>::deserialize(deserializer)?
+        .map(|value| value.parse().map_err(de::Error::custom))
+        .transpose()
+}
+
+/// Deserializes a list of config file values from strings, using their `FromStr` implementation.
+fn parsed_list<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: Display,
+{
+    Vec::<String>::deserialize(deserializer)?
+        .into_iter()
+        .map(|value| value.parse().map_err(de::Error::custom))
+        .collect()
+}
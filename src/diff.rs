@@ -0,0 +1,190 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::account::Account;
+use crate::output::OutputFormat;
+
+/// How a client's account differs between two account files.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Change {
+    // The client only has an account in the new file.
+    Added,
+    // The client only has an account in the old file.
+    Removed,
+    // A field of the client's account has a different value in the new file.
+    Changed,
+}
+
+/// A difference between two account files. Changed accounts have a difference for each field that
+/// changed, holding its old and new values.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Difference {
+    pub client: u16,
+    pub change: Change,
+    pub field: Option<&'static str>,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+/// Reads the accounts in a file written in the given format, keyed by client. Fails if the file
+/// cannot be read, or has more than one account for a client.
+pub fn read_accounts(path: &Path, format: OutputFormat) -> Result<BTreeMap<u16, Account>, String> {
+    let open = || {
+        File::open(path)
+            .map(BufReader::new)
+            .map_err(|e| e.to_string())
+    };
+    let accounts: Vec<Account> = match format {
+        OutputFormat::Csv => csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(open()?)
+            .deserialize()
+            .collect::<Result<_, _>>()
+            .map_err(|e| e.to_string())?,
+        OutputFormat::Json => serde_json::from_reader(open()?).map_err(|e| e.to_string())?,
+        OutputFormat::Jsonl => open()?
+            .lines()
+            .filter(|line| line.as_ref().map_or(true, |line| !line.trim().is_empty()))
+            .map(|line| {
+                let line = line.map_err(|e| e.to_string())?;
+                serde_json::from_str(&line).map_err(|e| e.to_string())
+            })
+            .collect::<Result<_, String>>()?,
+        #[cfg(feature = "parquet")]
+        OutputFormat::Parquet => crate::columnar::read_accounts(path)?,
+    };
+
+    let mut by_client = BTreeMap::new();
+    for account in accounts {
+        if by_client.insert(account.client, account).is_some() {
+            return Err(format!(
+                "client {} has more than one account",
+                account.client
+            ));
+        }
+    }
+    Ok(by_client)
+}
+
+/// Lists the differences between two sets of accounts, in order of client. Amounts are compared
+/// exactly.
+pub fn diff_accounts(
+    old: &BTreeMap<u16, Account>,
+    new: &BTreeMap<u16, Account>,
+) -> Vec<Difference> {
+    let mut clients: Vec<u16> = old.keys().chain(new.keys()).copied().collect();
+    clients.sort_unstable();
+    clients.dedup();
+
+    let mut differences = Vec::new();
+    for client in clients {
+        let (old, new) = match (old.get(&client), new.get(&client)) {
+            (Some(old), Some(new)) => (old, new),
+            (old, _) => {
+                differences.push(Difference {
+                    client,
+                    change: if old.is_some() {
+                        Change::Removed
+                    } else {
+                        Change::Added
+                    },
+                    field: None,
+                    old: None,
+                    new: None,
+                });
+                continue;
+            }
+        };
+
+        let amounts = [
+            ("available", old.available, new.available),
+            ("pending", old.pending, new.pending),
+            ("held", old.held, new.held),
+            ("total", old.total, new.total),
+        ]
+        .map(|(field, old, new)| (field, old != new, old.to_string(), new.to_string()));
+        let flags = [
+            ("locked", old.locked, new.locked),
+            ("closed", old.closed, new.closed),
+        ]
+        .map(|(field, old, new)| (field, old != new, old.to_string(), new.to_string()));
+        for (field, changed, old, new) in amounts.into_iter().chain(flags) {
+            if changed {
+                differences.push(Difference {
+                    client,
+                    change: Change::Changed,
+                    field: Some(field),
+                    old: Some(old),
+                    new: Some(new),
+                });
+            }
+        }
+    }
+    differences
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use crate::account::Account;
+    use crate::diff::{diff_accounts, Change};
+    use crate::transaction::Amount;
+
+    #[test]
+    fn differences() {
+        let account = |client, available| Account {
+            available: Amount(available),
+            total: Amount(available),
+            ..Account::new(client)
+        };
+        let old = BTreeMap::from([(1, account(1, 10_000)), (2, account(2, 0))]);
+        let new = BTreeMap::from([
+            (
+                1,
+                Account {
+                    locked: true,
+                    ..account(1, 10_001)
+                },
+            ),
+            (3, account(3, 0)),
+        ]);
+
+        let differences: Vec<_> = diff_accounts(&old, &new)
+            .into_iter()
+            .map(|difference| {
+                (
+                    difference.client,
+                    difference.change,
+                    difference.field,
+                    difference.old,
+                    difference.new,
+                )
+            })
+            .collect();
+        let changed = |field, old: &str, new: &str| {
+            (
+                1,
+                Change::Changed,
+                Some(field),
+                Some(old.to_string()),
+                Some(new.to_string()),
+            )
+        };
+        assert_eq!(
+            differences,
+            vec![
+                changed("available", "1.0", "1.0001"),
+                changed("total", "1.0", "1.0001"),
+                changed("locked", "false", "true"),
+                (2, Change::Removed, None, None, None),
+                (3, Change::Added, None, None, None),
+            ]
+        );
+    }
+}
@@ -0,0 +1,901 @@
+use std::cmp::min;
+use std::collections::HashMap;
+use std::time::Instant;
+
+use tracing::debug;
+
+use crate::account::Account;
+use crate::admin::AdminAction;
+use crate::expiry::{DisputableTransactions, DisputeDeadlines, DisputeWindows, ExpiryAction};
+use crate::history::{ClientHistory, HistoryEntry};
+use crate::hold::{HoldPeriods, PendingDeposits};
+use crate::ledger::{JournalEntry, Ledger, LedgerAccount};
+use crate::limits::RecentWithdrawals;
+use crate::metrics::Metrics;
+use crate::policy::{DuplicateIds, NegativeBalance, Policy};
+use crate::reconciliation::Reconciliation;
+use crate::rejection::Rejection;
+use crate::rules::{Flag, Rule, Verdict};
+use crate::transaction::{Amount, Transaction, TxType};
+
+/// Account balances, along with the state needed to apply further transactions to them. Balances
+/// are derived from the ledger, which every change in balance is posted to.
+#[derive(Default)]
+pub struct Engine {
+    pub accounts: HashMap<u16, Account>,
+    pub admin_log: Vec<AdminAction>,
+    pub ledger: Ledger,
+    // Funds moved into and out of client accounts so far, for reconciling with their balances.
+    control_totals: Reconciliation,
+    // Applied transactions, kept only if a statement or one of the rules needs them.
+    pub history: Vec<HistoryEntry>,
+    keep_history: bool,
+    // Positions of each client's entries in the history.
+    client_history: HashMap<u16, Vec<usize>>,
+    // Applied transactions that rules flagged.
+    pub flags: Vec<Flag>,
+    // Counts and processing latencies of the transactions applied or rejected.
+    pub metrics: Metrics,
+    transactions: DisputableTransactions,
+    disputed_transactions: HashMap<u32, Transaction>,
+    hold_periods: HoldPeriods,
+    pending_deposits: PendingDeposits,
+    // Deposits that were disputed while still on hold, along with when they would have matured.
+    disputed_pending_deposits: HashMap<u32, u64>,
+    dispute_windows: DisputeWindows,
+    dispute_deadlines: DisputeDeadlines,
+    policy: Policy,
+    recent_withdrawals: RecentWithdrawals,
+    rules: Vec<Box<dyn Rule>>,
+    // Timestamp of the latest timestamped transaction applied or rejected so far.
+    now: Option<u64>,
+}
+
+impl Engine {
+    pub fn new() -> Engine {
+        Engine::default()
+    }
+
+    /// Holds deposits for the given periods before they may be withdrawn.
+    pub fn with_hold_periods(mut self, hold_periods: HoldPeriods) -> Engine {
+        self.hold_periods = hold_periods;
+        self
+    }
+
+    /// Limits how old a disputed deposit may be, and how long disputes may stay open.
+    pub fn with_dispute_windows(mut self, dispute_windows: DisputeWindows) -> Engine {
+        self.dispute_windows = dispute_windows;
+        self
+    }
+
+    /// Applies the business rules given by the policy.
+    pub fn with_policy(mut self, policy: Policy) -> Engine {
+        self.policy = policy;
+        self
+    }
+
+    /// Keeps every journal entry posted to the ledger, so that the journal can be written out.
+    pub fn with_journal(mut self) -> Engine {
+        self.ledger = Ledger::with_journal();
+        self
+    }
+
+    /// Keeps a history of applied transactions and the balances after each, so that statements
+    /// can be written.
+    pub fn with_history(mut self) -> Engine {
+        self.keep_history = true;
+        self
+    }
+
+    /// Adds a rule to check transactions with before they are applied, after any rules added
+    /// before it. The history is kept if the rule uses it.
+    pub fn with_rule(mut self, rule: Box<dyn Rule>) -> Engine {
+        self.keep_history |= rule.uses_history();
+        self.rules.push(rule);
+        self
+    }
+
+    /// The funds moved into and out of client accounts so far, without their closing balances.
+    pub fn control_totals(&self) -> Reconciliation {
+        self.control_totals
+    }
+
+    /// The number of disputes that haven't been resolved or charged back.
+    pub fn open_disputes(&self) -> usize {
+        self.disputed_transactions.len()
+    }
+
+    /// The number of locked accounts.
+    pub fn locked_accounts(&self) -> usize {
+        self.accounts
+            .values()
+            .filter(|account| account.locked)
+            .count()
+    }
+
+    /// Returns every account, or only the given client's account.
+    pub fn client_accounts(&self, client: Option<u16>) -> Vec<&Account> {
+        self.accounts
+            .values()
+            .filter(|account| client.is_none_or(|client| account.client == client))
+            .collect()
+    }
+
+    /// Applies a transaction of any type, counting it in the metrics whether or not it is rejected.
+    pub fn apply(&mut self, transaction: &Transaction) -> Result<(), Rejection> {
+        let started = Instant::now();
+        let result = self.try_apply(transaction);
+        self.metrics
+            .record(transaction.tx_type, result.is_ok(), started.elapsed());
+        result
+    }
+
+    fn try_apply(&mut self, transaction: &Transaction) -> Result<(), Rejection> {
+        self.advance_clock(transaction)?;
+
+        if self.policy.duplicate_ids == DuplicateIds::Reject
+            && matches!(transaction.tx_type, TxType::Deposit | TxType::Withdrawal)
+            && self.transactions.contains(transaction.id)
+        {
+            return Err(Rejection::DuplicateTransaction);
+        }
+        let flags = self.check_rules(transaction)?;
+
+        let result = match transaction.tx_type {
+            TxType::Deposit => self.deposit(transaction),
+            TxType::Withdrawal => self.withdrawal(transaction),
+            TxType::Dispute => self.dispute(transaction),
+            TxType::Resolve => self.resolve(transaction),
+            TxType::Chargeback => self.chargeback(transaction),
+            TxType::Lock => self.lock(transaction),
+            TxType::Unlock => self.unlock(transaction),
+            TxType::Close => self.close(transaction),
+        };
+        if result.is_ok() {
+            if matches!(transaction.tx_type, TxType::Deposit | TxType::Withdrawal) {
+                // Ids should be unique, but if not, disputes refer to the first use of an id.
+                self.transactions
+                    .insert(transaction, self.dispute_windows.max_deposit_age);
+            }
+            self.record(
+                transaction.clone(),
+                flags.iter().map(|flag| flag.code).collect(),
+            );
+            self.flags.extend(flags);
+        }
+        result
+    }
+
+    /// Checks a transaction against each rule in order, stopping at the first rule to reject it.
+    /// Returns the flags raised by the rules, if it isn't rejected.
+    fn check_rules(&self, transaction: &Transaction) -> Result<Vec<Flag>, Rejection> {
+        let account = self.accounts.get(&transaction.client);
+        let history = self.client_history(transaction.client);
+        let mut flags = Vec::new();
+        for rule in &self.rules {
+            match rule.check(transaction, account, history) {
+                Verdict::Allow => {}
+                Verdict::Reject => return Err(Rejection::RuleViolation(rule.name())),
+                Verdict::Flag => flags.push(Flag {
+                    client: transaction.client,
+                    tx: transaction.id,
+                    tx_type: transaction.tx_type,
+                    code: rule.name(),
+                    timestamp: transaction.timestamp,
+                }),
+            }
+        }
+        Ok(flags)
+    }
+
+    /// Returns the history of the given client's transactions.
+    pub fn client_history(&self, client: u16) -> ClientHistory<'_> {
+        let entries = self
+            .client_history
+            .get(&client)
+            .map_or(&[][..], Vec::as_slice);
+        ClientHistory::new(&self.history, entries)
+    }
+
+    /// Adds an applied transaction to the history, along with the resulting state of the account
+    /// and the codes of any flags raised on it, if the history is being kept.
+    fn record(&mut self, transaction: Transaction, flags: Vec<&'static str>) {
+        if !self.keep_history {
+            return;
+        }
+        let account = self
+            .accounts
+            .get(&transaction.client)
+            .copied()
+            .unwrap_or_else(|| Account::new(transaction.client));
+        self.client_history
+            .entry(transaction.client)
+            .or_default()
+            .push(self.history.len());
+        self.history.push(HistoryEntry {
+            transaction,
+            account,
+            flags,
+        });
+    }
+
+    /// Moves the engine's clock forward to the transaction's timestamp, if it has one. Timestamps
+    /// must never go backwards, since balances depend on the order transactions are applied in.
+    /// The clock moves before the transaction is checked, since deposits that have matured or
+    /// disputes that have expired by then affect whether it is applied, so the time of a rejected
+    /// transaction still counts.
+    fn advance_clock(&mut self, transaction: &Transaction) -> Result<(), Rejection> {
+        if let Some(timestamp) = transaction.timestamp {
+            if self.now.is_some_and(|now| timestamp < now) {
+                return Err(Rejection::OutOfOrder);
+            }
+            self.advance_to(timestamp);
+        }
+        Ok(())
+    }
+
+    /// Moves the engine's clock forward to the given time, maturing deposits and expiring disputes
+    /// as needed. The clock never moves backwards.
+    pub fn advance_to(&mut self, now: u64) {
+        if self.now.is_some_and(|current| current > now) {
+            return;
+        }
+        self.now = Some(now);
+        self.mature_deposits(now);
+        self.expire_disputes(now);
+        // Ids of transactions too old to dispute are only needed to reject reused ids.
+        self.transactions
+            .close(now, self.policy.duplicate_ids == DuplicateIds::Reject);
+    }
+
+    /// Makes pending deposits that have finished their hold period available for withdrawal.
+    fn mature_deposits(&mut self, now: u64) {
+        while let Some((id, client, amount)) = self.pending_deposits.pop_matured(now) {
+            debug!(client, tx = id, "Deposit matured");
+            self.post(
+                id,
+                TxType::Deposit,
+                LedgerAccount::Pending(client),
+                LedgerAccount::Available(client),
+                amount,
+            );
+        }
+    }
+
+    /// Settles disputes that have passed their deadline, as if the client had sent a resolve or
+    /// chargeback at the current time.
+    fn expire_disputes(&mut self, now: u64) {
+        while let Some(id) = self.dispute_deadlines.pop_expired(now) {
+            let Some(disputed_tx) = self.disputed_transactions.get(&id) else {
+                continue;
+            };
+            let tx_type = match self.dispute_windows.on_expiry {
+                ExpiryAction::Resolve => TxType::Resolve,
+                ExpiryAction::Chargeback => TxType::Chargeback,
+            };
+            debug!(client = disputed_tx.client, tx = id, settlement = ?tx_type, "Dispute expired");
+            let settlement = Transaction {
+                tx_type,
+                client: disputed_tx.client,
+                id,
+                amount: None,
+                operator: None,
+                reason: None,
+                timestamp: Some(now),
+            };
+
+            // The dispute is known to be open, so settling it can only be rejected if charging back
+            // a withdrawal would take the client's balance too high, which leaves it open.
+            let result = match tx_type {
+                TxType::Chargeback => self.chargeback(&settlement),
+                _ => self.resolve(&settlement),
+            };
+            if result.is_ok() {
+                self.record(settlement, Vec::new());
+            }
+        }
+    }
+
+    /// Adds specified amount to account balance. The amount is available immediately, unless the
+    /// client has a hold period, in which case it is pending until the hold period has passed.
+    pub fn deposit(&mut self, transaction: &Transaction) -> Result<(), Rejection> {
+        let account = self
+            .accounts
+            .entry(transaction.client)
+            .or_insert_with(|| Account::new(transaction.client));
+        account.check_open()?;
+
+        let amount = transaction.amount.unwrap_or_default();
+        self.check_credit(transaction.client, amount)?;
+        // Hold periods are measured from the engine's clock, so without timestamps there is no hold.
+        let destination = match (self.hold_periods.for_client(transaction.client), self.now) {
+            (Some(hold_period), Some(now)) => {
+                self.pending_deposits.insert(
+                    transaction.id,
+                    transaction.client,
+                    amount,
+                    now.saturating_add(hold_period),
+                );
+                LedgerAccount::Pending(transaction.client)
+            }
+            _ => LedgerAccount::Available(transaction.client),
+        };
+        self.post(
+            transaction.id,
+            TxType::Deposit,
+            LedgerAccount::Clearing,
+            destination,
+            amount,
+        );
+        Ok(())
+    }
+
+    /// Reduces available account balance by specified amount, if the client's withdrawal limits
+    /// allow it.
+    pub fn withdrawal(&mut self, transaction: &Transaction) -> Result<(), Rejection> {
+        let account = self
+            .accounts
+            .entry(transaction.client)
+            .or_insert_with(|| Account::new(transaction.client));
+        account.check_open()?;
+
+        // Withdrawals over the client's limits are rejected, whatever their balance.
+        let amount = transaction.amount.unwrap_or_default();
+        self.recent_withdrawals
+            .check(
+                transaction.client,
+                amount,
+                self.policy.limits.for_client(transaction.client),
+                self.policy.limits.window,
+                self.now,
+            )
+            .map_err(Rejection::WithdrawalLimit)?;
+
+        // If an overflow occurs (account balance is insufficient), we ignore the withdrawal.
+        account
+            .available
+            .checked_sub(amount)
+            .ok_or(Rejection::InsufficientFunds)?;
+        self.post(
+            transaction.id,
+            TxType::Withdrawal,
+            LedgerAccount::Available(transaction.client),
+            LedgerAccount::Clearing,
+            amount,
+        );
+        self.recent_withdrawals
+            .insert(transaction.client, amount, self.now);
+        Ok(())
+    }
+
+    /// Disputes specified transaction, if it has been applied.
+    pub fn dispute(&mut self, transaction: &Transaction) -> Result<(), Rejection> {
+        // If the disputed transaction doesn't exist, or is too old to have been kept, we do nothing.
+        let disputed_tx = self.transactions.get(transaction.id)?.clone();
+
+        // Don't allow disputing someone else's transaction.
+        if transaction.client != disputed_tx.client {
+            return Err(Rejection::NotOwner);
+        }
+        let account = self
+            .accounts
+            .entry(transaction.client)
+            .or_insert_with(|| Account::new(transaction.client));
+
+        // Only allow disputing the types of transaction the policy allows.
+        if !self.policy.disputable.contains(&disputed_tx.tx_type) {
+            return Err(Rejection::NotDisputable);
+        }
+
+        // Closed accounts have nothing left to hold.
+        account.check_not_closed()?;
+
+        // Don't allow disputing deposits that are too old, if we know how old they are.
+        if let (Some(max_age), Some(deposited_at), Some(now)) = (
+            self.dispute_windows.max_deposit_age,
+            disputed_tx.timestamp,
+            self.now,
+        ) {
+            if now.saturating_sub(deposited_at) > max_age {
+                return Err(Rejection::DisputeWindowClosed);
+            }
+        }
+
+        if let Some((_, amount, matures_at)) = self.pending_deposits.remove(disputed_tx.id) {
+            // The deposit is still pending, so its funds can't have been withdrawn yet.
+            self.disputed_pending_deposits
+                .insert(disputed_tx.id, matures_at);
+            self.post(
+                disputed_tx.id,
+                disputed_tx.tx_type,
+                LedgerAccount::Pending(transaction.client),
+                LedgerAccount::Held(transaction.client),
+                amount,
+            );
+        } else if disputed_tx.tx_type == TxType::Withdrawal {
+            // The withdrawn funds are returned to the client, but held until the dispute is
+            // settled.
+            self.check_credit(transaction.client, disputed_tx.amount.unwrap_or_default())?;
+            self.post(
+                disputed_tx.id,
+                disputed_tx.tx_type,
+                LedgerAccount::Clearing,
+                LedgerAccount::Held(transaction.client),
+                disputed_tx.amount.unwrap_or_default(),
+            );
+        } else {
+            // If the disputed amount is more than the available balance, the best we can do is
+            // hold the available balance, unless the policy is to reject the dispute. This should
+            // rarely happen when deposits are held for a suitable holding period.
+            let disputed_amount = disputed_tx.amount.unwrap_or_default();
+            if account.available < disputed_amount
+                && self.policy.negative_balance == NegativeBalance::Reject
+            {
+                return Err(Rejection::InsufficientFunds);
+            }
+            let amount = min(account.available, disputed_amount);
+            self.post(
+                disputed_tx.id,
+                disputed_tx.tx_type,
+                LedgerAccount::Available(transaction.client),
+                LedgerAccount::Held(transaction.client),
+                amount,
+            );
+        }
+
+        // Start the clock on settling the dispute.
+        if let (Some(deadline), Some(now)) = (self.dispute_windows.deadline, self.now) {
+            self.dispute_deadlines
+                .insert(disputed_tx.id, now.saturating_add(deadline));
+        }
+
+        self.disputed_transactions
+            .insert(disputed_tx.id, disputed_tx);
+        Ok(())
+    }
+
+    /// Resolves disputed transaction, if it exists.
+    pub fn resolve(&mut self, transaction: &Transaction) -> Result<(), Rejection> {
+        let (disputed_tx, matures_at) = self.settle_dispute(transaction)?;
+
+        // A deposit disputed while on hold goes back on hold until it would have matured, so that
+        // disputing it doesn't release it early.
+        if let Some(matures_at) = matures_at {
+            let held = self
+                .accounts
+                .get(&transaction.client)
+                .map_or(Amount(0), |account| account.held);
+            let amount = min(held, disputed_tx.amount.unwrap_or_default());
+            self.post(
+                transaction.id,
+                disputed_tx.tx_type,
+                LedgerAccount::Held(transaction.client),
+                LedgerAccount::Pending(transaction.client),
+                amount,
+            );
+            self.pending_deposits
+                .insert(transaction.id, transaction.client, amount, matures_at);
+            if let Some(now) = self.now {
+                self.mature_deposits(now);
+            }
+        }
+
+        // A disputed withdrawal stands, so the funds held for it are withdrawn again.
+        if disputed_tx.tx_type == TxType::Withdrawal {
+            let held = self
+                .accounts
+                .get(&transaction.client)
+                .map_or(Amount(0), |account| account.held);
+            self.post(
+                transaction.id,
+                disputed_tx.tx_type,
+                LedgerAccount::Held(transaction.client),
+                LedgerAccount::Clearing,
+                min(held, disputed_tx.amount.unwrap_or_default()),
+            );
+        }
+        let account = self
+            .accounts
+            .entry(transaction.client)
+            .or_insert_with(|| Account::new(transaction.client));
+
+        // Get the total remaining amount disputed for the given account.
+        let amount_disputed = self
+            .disputed_transactions
+            .values()
+            .fold(Amount(0), |acc, tx| {
+                if tx.client == transaction.client {
+                    acc + tx.amount.unwrap_or_default()
+                } else {
+                    acc
+                }
+            });
+
+        // Set held equal to the amount disputed, unless the settled balance is smaller. Pending funds
+        // are left to mature as usual.
+        let (available, previously_held) = (account.available, account.held);
+        let held = min(amount_disputed, available + previously_held);
+        if let Some(released) = previously_held.checked_sub(held) {
+            self.post(
+                transaction.id,
+                disputed_tx.tx_type,
+                LedgerAccount::Held(transaction.client),
+                LedgerAccount::Available(transaction.client),
+                released,
+            );
+        } else {
+            self.post(
+                transaction.id,
+                disputed_tx.tx_type,
+                LedgerAccount::Available(transaction.client),
+                LedgerAccount::Held(transaction.client),
+                held.saturating_sub(previously_held),
+            );
+        }
+        Ok(())
+    }
+
+    /// Charges back disputed transaction, if it exists.
+    pub fn chargeback(&mut self, transaction: &Transaction) -> Result<(), Rejection> {
+        // Charging back a withdrawal returns whatever wasn't held for it to the client as well.
+        if let Some(disputed_tx) = self.disputed_transactions.get(&transaction.id) {
+            if disputed_tx.tx_type == TxType::Withdrawal && disputed_tx.client == transaction.client
+            {
+                let held = self
+                    .accounts
+                    .get(&transaction.client)
+                    .map_or(Amount(0), |account| account.held);
+                let shortfall = disputed_tx.amount.unwrap_or_default().saturating_sub(held);
+                self.check_credit(transaction.client, shortfall)?;
+            }
+        }
+        let (disputed_tx, _) = self.settle_dispute(transaction)?;
+        let account = self
+            .accounts
+            .entry(transaction.client)
+            .or_insert_with(|| Account::new(transaction.client));
+
+        // Lock account, unless the policy says otherwise.
+        if self.policy.lock_on_chargeback {
+            account.locked = true;
+        }
+
+        // Return a disputed deposit from held funds, or release the funds held for a disputed
+        // withdrawal to the client. If too little was held, the platform covers the difference.
+        let amount = disputed_tx.amount.unwrap_or_default();
+        let covered = min(account.held, amount);
+        let (from, to) = match disputed_tx.tx_type {
+            TxType::Withdrawal => (
+                LedgerAccount::Held(transaction.client),
+                LedgerAccount::Available(transaction.client),
+            ),
+            _ => (
+                LedgerAccount::Held(transaction.client),
+                LedgerAccount::Clearing,
+            ),
+        };
+        self.post(transaction.id, disputed_tx.tx_type, from, to, covered);
+        self.post(
+            transaction.id,
+            disputed_tx.tx_type,
+            LedgerAccount::ChargebackLoss,
+            to,
+            amount.saturating_sub(covered),
+        );
+        Ok(())
+    }
+
+    /// Closes the dispute that a resolve or chargeback refers to, returning the disputed
+    /// transaction, and when it would have matured if it was a deposit disputed while on hold.
+    /// Fails if the transaction isn't disputed, or if the dispute is another client's.
+    fn settle_dispute(
+        &mut self,
+        transaction: &Transaction,
+    ) -> Result<(Transaction, Option<u64>), Rejection> {
+        match self.disputed_transactions.get(&transaction.id) {
+            None => return Err(Rejection::NotDisputed),
+            Some(disputed_tx) if disputed_tx.client != transaction.client => {
+                return Err(Rejection::NotOwner);
+            }
+            Some(_) => {}
+        }
+        self.dispute_deadlines.remove(transaction.id);
+        let disputed_tx = self
+            .disputed_transactions
+            .remove(&transaction.id)
+            .expect("transaction is disputed");
+        Ok((
+            disputed_tx,
+            self.disputed_pending_deposits.remove(&transaction.id),
+        ))
+    }
+
+    /// Checks that moving the given amount into a client's account from outside it wouldn't take
+    /// their total balance past the largest amount that can be represented.
+    fn check_credit(&self, client: u16, amount: Amount) -> Result<(), Rejection> {
+        let total = self
+            .accounts
+            .get(&client)
+            .map_or(Amount(0), |account| account.total);
+        total
+            .checked_add(amount)
+            .map(|_| ())
+            .ok_or(Rejection::BalanceOverflow)
+    }
+
+    /// Posts a journal entry to the ledger, and updates the balances of any client accounts
+    /// involved. `tx_type` is the type of the deposit or withdrawal that the entry comes from.
+    fn post(
+        &mut self,
+        id: u32,
+        tx_type: TxType,
+        from: LedgerAccount,
+        to: LedgerAccount,
+        amount: Amount,
+    ) {
+        if amount == Amount(0) {
+            return;
+        }
+        let entry = JournalEntry {
+            tx: id,
+            from,
+            to,
+            amount,
+        };
+        self.control_totals
+            .post(&entry, tx_type == TxType::Withdrawal);
+        self.ledger.post(entry);
+
+        for client in [from.client(), to.client()].into_iter().flatten() {
+            let account = self
+                .accounts
+                .entry(client)
+                .or_insert_with(|| Account::new(client));
+            account.available = self.ledger.client_balance(LedgerAccount::Available(client));
+            account.pending = self.ledger.client_balance(LedgerAccount::Pending(client));
+            account.held = self.ledger.client_balance(LedgerAccount::Held(client));
+            account.total = account.available + account.pending + account.held;
+        }
+    }
+
+    /// Manually freezes an account, preventing further deposits and withdrawals.
+    pub fn lock(&mut self, transaction: &Transaction) -> Result<(), Rejection> {
+        self.admin(transaction, |account| {
+            account.check_not_closed()?;
+            account.locked = true;
+            Ok(())
+        })
+    }
+
+    /// Unfreezes an account, whether it was locked manually or by a chargeback.
+    pub fn unlock(&mut self, transaction: &Transaction) -> Result<(), Rejection> {
+        self.admin(transaction, |account| {
+            account.check_not_closed()?;
+            account.locked = false;
+            Ok(())
+        })
+    }
+
+    /// Closes an account permanently. Only accounts with a zero balance may be closed.
+    pub fn close(&mut self, transaction: &Transaction) -> Result<(), Rejection> {
+        self.admin(transaction, |account| {
+            account.check_not_closed()?;
+            if account.total != Amount(0) {
+                return Err(Rejection::NonZeroBalance);
+            }
+            account.closed = true;
+            Ok(())
+        })
+    }
+
+    /// Applies an admin action to an existing account, recording it in the admin log if it
+    /// succeeds. Admin actions must name the operator responsible and the reason for the action.
+    fn admin<F>(&mut self, transaction: &Transaction, action: F) -> Result<(), Rejection>
+    where
+        F: FnOnce(&mut Account) -> Result<(), Rejection>,
+    {
+        let (Some(operator), Some(reason)) = (transaction.operator, &transaction.reason) else {
+            return Err(Rejection::MissingAdminDetails);
+        };
+        if reason.is_empty() {
+            return Err(Rejection::MissingAdminDetails);
+        }
+
+        let account = self
+            .accounts
+            .get_mut(&transaction.client)
+            .ok_or(Rejection::UnknownAccount)?;
+        action(account)?;
+
+        self.admin_log.push(AdminAction {
+            tx_type: transaction.tx_type,
+            client: transaction.client,
+            id: transaction.id,
+            operator,
+            reason: reason.clone(),
+            timestamp: transaction.timestamp,
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use proptest::prelude::*;
+
+    use crate::engine::Engine;
+    use crate::hold::HoldPeriods;
+    use crate::ledger::LedgerAccount;
+    use crate::reconciliation::Reconciliation;
+    use crate::transaction::{Amount, Transaction, TxType};
+
+    const CLIENTS: u16 = 4;
+
+    /// Generates streams of client transactions, with timestamps that never go backwards. Deposits
+    /// and withdrawals have unique ids, while disputes, resolves and chargebacks mostly refer to
+    /// earlier transactions, usually by the same client, and otherwise to an id that is never used.
+    fn transactions() -> impl Strategy<Value = Vec<Transaction>> {
+        let row = (
+            0..5u8,
+            1..=CLIENTS,
+            1..=100_000u64,
+            any::<u32>(),
+            any::<bool>(),
+            0..5u64,
+        );
+        prop::collection::vec(row, 0..60).prop_map(|rows| {
+            let mut transactions: Vec<Transaction> = Vec::new();
+            let mut timestamp = 0;
+            for (id, (kind, client, amount, disputed, same_client, elapsed)) in (1..).zip(rows) {
+                timestamp += elapsed;
+                let disputed = disputed % id;
+                let disputed_client = (disputed > 0 && same_client)
+                    .then(|| transactions[disputed as usize - 1].client);
+                let (tx_type, id, client, amount) = match kind {
+                    0 => (TxType::Deposit, id, client, Some(Amount(amount))),
+                    1 => (TxType::Withdrawal, id, client, Some(Amount(amount))),
+                    kind => {
+                        let tx_type = match kind {
+                            2 => TxType::Dispute,
+                            3 => TxType::Resolve,
+                            _ => TxType::Chargeback,
+                        };
+                        (tx_type, disputed, disputed_client.unwrap_or(client), None)
+                    }
+                };
+                transactions.push(Transaction {
+                    tx_type,
+                    client,
+                    id,
+                    amount,
+                    operator: None,
+                    reason: None,
+                    timestamp: Some(timestamp),
+                });
+            }
+            transactions
+        })
+    }
+
+    /// Generates hold periods, by default and for particular clients, or none at all. Periods are
+    /// often longer than a whole stream of transactions, so that deposits are disputed, resolved
+    /// and charged back while still on hold.
+    fn hold_periods() -> impl Strategy<Value = HoldPeriods> {
+        let period = prop::option::of(1..500u64);
+        (
+            period.clone(),
+            prop::collection::vec(period, CLIENTS as usize),
+        )
+            .prop_map(|(default, periods)| HoldPeriods {
+                default,
+                clients: (1..)
+                    .zip(periods)
+                    .filter_map(|(client, period)| Some((client, period?)))
+                    .collect(),
+            })
+    }
+
+    /// Applies the transactions in order, returning the engine and whether each was applied.
+    fn apply_all<'a>(
+        transactions: impl IntoIterator<Item = &'a Transaction>,
+        hold_periods: &HoldPeriods,
+    ) -> (Engine, Vec<bool>) {
+        let mut engine = Engine::new().with_hold_periods(hold_periods.clone());
+        let applied = transactions
+            .into_iter()
+            .map(|transaction| engine.apply(transaction).is_ok())
+            .collect();
+        (engine, applied)
+    }
+
+    proptest! {
+        // Some bugs only show under particular orders of the engine's maps, so run more cases than
+        // the default.
+        #![proptest_config(ProptestConfig::with_cases(2000))]
+
+        #[test]
+        fn balances(transactions in transactions(), hold_periods in hold_periods()) {
+            let (engine, _) = apply_all(&transactions, &hold_periods);
+
+            // Deposits only become available once their hold period is over, even if they are
+            // disputed and resolved while on hold, so if every client's deposits are held for
+            // longer than the whole stream, no funds ever become available.
+            let duration = transactions
+                .last()
+                .and_then(|transaction| transaction.timestamp)
+                .unwrap_or(0);
+            let held_throughout = transactions.iter().all(|transaction| {
+                hold_periods
+                    .for_client(transaction.client)
+                    .is_some_and(|period| period > duration)
+            });
+
+            prop_assert_eq!(engine.ledger.check(), Ok(()));
+            prop_assert!(Reconciliation::new(&engine).balanced);
+            for account in engine.accounts.values() {
+                if held_throughout {
+                    prop_assert_eq!(account.available, Amount(0));
+                }
+                prop_assert_eq!(
+                    account.available + account.pending + account.held,
+                    account.total
+                );
+                // Balances are unsigned, so the ledger is where they could go negative.
+                for ledger_account in [
+                    LedgerAccount::Available(account.client),
+                    LedgerAccount::Pending(account.client),
+                    LedgerAccount::Held(account.client),
+                ] {
+                    prop_assert!(engine.ledger.balance(ledger_account) >= 0);
+                }
+                prop_assert_eq!(
+                    engine.ledger.balance(LedgerAccount::Available(account.client)),
+                    i128::from(account.available.0)
+                );
+                prop_assert_eq!(
+                    engine.ledger.balance(LedgerAccount::Pending(account.client)),
+                    i128::from(account.pending.0)
+                );
+                prop_assert_eq!(
+                    engine.ledger.balance(LedgerAccount::Held(account.client)),
+                    i128::from(account.held.0)
+                );
+            }
+        }
+
+        #[test]
+        fn sharding(
+            transactions in transactions(),
+            hold_periods in hold_periods(),
+            shards in 1..=CLIENTS,
+        ) {
+            let (serial, serial_applied) = apply_all(&transactions, &hold_periods);
+
+            // Clients' accounts are independent, so splitting the stream by client and applying
+            // each part to its own engine gives the same accounts and outcomes, once each engine's
+            // clock has caught up with the end of the whole stream.
+            let mut accounts = HashMap::new();
+            let mut applied = vec![false; transactions.len()];
+            for shard in 0..shards {
+                let (positions, shard_transactions): (Vec<_>, Vec<_>) = transactions
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, transaction)| transaction.client % shards == shard)
+                    .unzip();
+                let (mut engine, shard_applied) = apply_all(shard_transactions, &hold_periods);
+                if let Some(end) = transactions.last().and_then(|transaction| transaction.timestamp) {
+                    engine.advance_to(end);
+                }
+                accounts.extend(engine.accounts);
+                for (position, shard_applied) in positions.into_iter().zip(shard_applied) {
+                    applied[position] = shard_applied;
+                }
+            }
+
+            prop_assert_eq!(accounts, serial.accounts);
+            prop_assert_eq!(applied, serial_applied);
+        }
+    }
+}
@@ -0,0 +1,132 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+use std::str::FromStr;
+
+use crate::rejection::Rejection;
+use crate::transaction::Transaction;
+
+/// Time limits on disputes, in seconds. Deposits older than `max_deposit_age` may not be disputed,
+/// and disputes left open for longer than `deadline` are settled according to `on_expiry`.
+#[derive(Default, Debug, Clone)]
+pub struct DisputeWindows {
+    pub max_deposit_age: Option<u64>,
+    pub deadline: Option<u64>,
+    pub on_expiry: ExpiryAction,
+}
+
+/// How to settle a dispute that has passed its deadline.
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub enum ExpiryAction {
+    #[default]
+    Resolve,
+    Chargeback,
+}
+
+impl FromStr for ExpiryAction {
+    type Err = String;
+
+    fn from_str(action: &str) -> Result<ExpiryAction, String> {
+        match action {
+            "resolve" => Ok(ExpiryAction::Resolve),
+            "chargeback" => Ok(ExpiryAction::Chargeback),
+            _ => Err(format!("expected resolve or chargeback, found {action}")),
+        }
+    }
+}
+
+/// Deadlines of open disputes, indexed both by disputed transaction id and by deadline.
+#[derive(Default)]
+pub struct DisputeDeadlines {
+    deadlines: HashMap<u32, u64>,
+    // Entries for disputes that were settled early are skipped when they reach the front.
+    queue: BinaryHeap<Reverse<(u64, u32)>>,
+}
+
+impl DisputeDeadlines {
+    pub fn insert(&mut self, id: u32, deadline: u64) {
+        self.deadlines.insert(id, deadline);
+        self.queue.push(Reverse((deadline, id)));
+    }
+
+    pub fn remove(&mut self, id: u32) {
+        self.deadlines.remove(&id);
+    }
+
+    /// Removes the next dispute to have passed its deadline by `now`, returning its id.
+    pub fn pop_expired(&mut self, now: u64) -> Option<u32> {
+        while let Some(&Reverse((deadline, id))) = self.queue.peek() {
+            if deadline > now {
+                return None;
+            }
+            self.queue.pop();
+            // A dispute that was settled and then disputed again will have a newer deadline.
+            if self.deadlines.get(&id) == Some(&deadline) {
+                self.deadlines.remove(&id);
+                return Some(id);
+            }
+        }
+        None
+    }
+}
+
+/// Applied deposits and withdrawals, retained so that disputes can refer back to them. A
+/// transaction is dropped once it is too old to dispute, so that retention is bounded by the
+/// dispute window rather than by the number of transactions applied. Its id is only kept if reused
+/// ids are to be rejected, in which case memory still grows by an id per transaction.
+#[derive(Default)]
+pub struct DisputableTransactions {
+    transactions: HashMap<u32, Transaction>,
+    // Ids of retained timestamped transactions, in the order they become too old to dispute.
+    closing: VecDeque<(u64, u32)>,
+    // Ids of dropped transactions, if they are being kept.
+    closed: HashSet<u32>,
+}
+
+impl DisputableTransactions {
+    /// Whether a transaction with the given id has been retained, even if only its id is left.
+    pub fn contains(&self, id: u32) -> bool {
+        self.transactions.contains_key(&id) || self.closed.contains(&id)
+    }
+
+    /// Returns the transaction with the given id, if it may still be disputed. A dropped
+    /// transaction whose id wasn't kept is unknown.
+    pub fn get(&self, id: u32) -> Result<&Transaction, Rejection> {
+        self.transactions
+            .get(&id)
+            .ok_or(if self.closed.contains(&id) {
+                Rejection::DisputeWindowClosed
+            } else {
+                Rejection::UnknownTransaction
+            })
+    }
+
+    /// Retains a transaction, unless one with the same id already has been. If it has a timestamp
+    /// and deposits older than `max_age` may not be disputed, it is dropped once it is that old.
+    pub fn insert(&mut self, transaction: &Transaction, max_age: Option<u64>) {
+        if self.contains(transaction.id) {
+            return;
+        }
+        self.transactions
+            .insert(transaction.id, transaction.clone());
+        if let (Some(max_age), Some(timestamp)) = (max_age, transaction.timestamp) {
+            // Timestamps never go backwards, so the queue stays in order.
+            self.closing
+                .push_back((timestamp.saturating_add(max_age), transaction.id));
+        }
+    }
+
+    /// Drops the transactions that are too old to dispute by `now`, keeping their ids if
+    /// `keep_ids` is set.
+    pub fn close(&mut self, now: u64, keep_ids: bool) {
+        while let Some(&(closes_at, id)) = self.closing.front() {
+            if closes_at >= now {
+                return;
+            }
+            self.closing.pop_front();
+            self.transactions.remove(&id);
+            if keep_ids {
+                self.closed.insert(id);
+            }
+        }
+    }
+}
@@ -0,0 +1,62 @@
+use serde::Serialize;
+
+use crate::account::Account;
+use crate::audit::Outcome;
+use crate::input::Location;
+use crate::rejection::Rejection;
+use crate::transaction::{Amount, Transaction, TxType};
+
+/// A step in explaining a client's account: a row of their input, what became of it, and their
+/// balances after it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Step {
+    pub file: String,
+    pub line: u64,
+    #[serde(rename = "type")]
+    pub tx_type: TxType,
+    #[serde(rename = "tx")]
+    pub id: u32,
+    pub amount: Option<Amount>,
+    pub timestamp: Option<u64>,
+    pub outcome: Outcome,
+    // The check or rule that rejected the transaction, if it was rejected.
+    pub reason: Option<String>,
+    // Names of the rules that flagged the transaction, separated by semicolons.
+    pub flags: String,
+    pub available: Amount,
+    pub pending: Amount,
+    pub held: Amount,
+    pub total: Amount,
+    pub locked: bool,
+}
+
+impl Step {
+    pub fn new(
+        location: &Location,
+        transaction: &Transaction,
+        result: Result<(), Rejection>,
+        flags: &[&str],
+        after: Account,
+    ) -> Step {
+        let (outcome, reason) = match result {
+            Ok(()) => (Outcome::Applied, None),
+            Err(e) => (Outcome::Rejected, Some(e.to_string())),
+        };
+        Step {
+            file: location.path.display().to_string(),
+            line: location.line,
+            tx_type: transaction.tx_type,
+            id: transaction.id,
+            amount: transaction.amount,
+            timestamp: transaction.timestamp,
+            outcome,
+            reason,
+            flags: flags.join(";"),
+            available: after.available,
+            pending: after.pending,
+            held: after.held,
+            total: after.total,
+            locked: after.locked,
+        }
+    }
+}
@@ -0,0 +1,30 @@
+use crate::account::Account;
+use crate::transaction::Transaction;
+
+/// A transaction that was applied by the engine, along with the state of the client's account
+/// immediately afterwards and the codes of any flags raised on it.
+#[derive(Debug, Clone)]
+pub struct HistoryEntry {
+    pub transaction: Transaction,
+    pub account: Account,
+    pub flags: Vec<&'static str>,
+}
+
+/// A view of the history of a single client's transactions.
+#[derive(Clone, Copy)]
+pub struct ClientHistory<'a> {
+    history: &'a [HistoryEntry],
+    // Positions of the client's entries in the history, in the order they were applied.
+    entries: &'a [usize],
+}
+
+impl<'a> ClientHistory<'a> {
+    pub fn new(history: &'a [HistoryEntry], entries: &'a [usize]) -> ClientHistory<'a> {
+        ClientHistory { history, entries }
+    }
+
+    /// Iterates over the client's entries, oldest first.
+    pub fn iter(self) -> impl DoubleEndedIterator<Item = &'a HistoryEntry> {
+        self.entries.iter().map(move |&index| &self.history[index])
+    }
+}
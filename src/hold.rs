@@ -0,0 +1,56 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+use crate::transaction::Amount;
+
+/// How long deposits are held before they may be withdrawn, in seconds. Clients without a hold
+/// period of their own use the default, if there is one.
+#[derive(Default, Debug, Clone)]
+pub struct HoldPeriods {
+    pub default: Option<u64>,
+    pub clients: HashMap<u16, u64>,
+}
+
+impl HoldPeriods {
+    pub fn for_client(&self, client: u16) -> Option<u64> {
+        self.clients.get(&client).copied().or(self.default)
+    }
+}
+
+/// Deposits whose funds have not yet matured, indexed both by transaction id and by when they
+/// mature.
+#[derive(Default)]
+pub struct PendingDeposits {
+    deposits: HashMap<u32, (u16, Amount, u64)>,
+    // Entries for deposits that were removed early are skipped when they reach the front.
+    maturities: BinaryHeap<Reverse<(u64, u32)>>,
+}
+
+impl PendingDeposits {
+    pub fn insert(&mut self, id: u32, client: u16, amount: Amount, matures_at: u64) {
+        self.deposits.insert(id, (client, amount, matures_at));
+        self.maturities.push(Reverse((matures_at, id)));
+    }
+
+    /// Removes a deposit before it matures, returning its client, amount and when it would have
+    /// matured.
+    pub fn remove(&mut self, id: u32) -> Option<(u16, Amount, u64)> {
+        self.deposits.remove(&id)
+    }
+
+    /// Removes the next deposit to have matured by `now`, returning its id, client and amount.
+    pub fn pop_matured(&mut self, now: u64) -> Option<(u32, u16, Amount)> {
+        while let Some(&Reverse((matures_at, id))) = self.maturities.peek() {
+            if matures_at > now {
+                return None;
+            }
+            self.maturities.pop();
+            // A deposit removed and inserted again matures at the same time, so only one of its
+            // entries is returned.
+            if let Some((client, amount, _)) = self.deposits.remove(&id) {
+                return Some((id, client, amount));
+            }
+        }
+        None
+    }
+}
@@ -0,0 +1,327 @@
+use std::fmt;
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::rc::Rc;
+use std::str::{self, FromStr};
+use std::vec;
+
+use tracing::error;
+
+#[cfg(feature = "parquet")]
+use crate::columnar::{BatchReader, ColumnarFormat};
+use crate::compression;
+use crate::strict;
+use crate::transaction::Transaction;
+
+/// Formats that transactions can be read in.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum InputFormat {
+    #[default]
+    Csv,
+    // One JSON object per line.
+    Jsonl,
+    #[cfg(feature = "parquet")]
+    Columnar(ColumnarFormat),
+}
+
+impl FromStr for InputFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<InputFormat, String> {
+        match format {
+            "csv" => Ok(InputFormat::Csv),
+            "jsonl" => Ok(InputFormat::Jsonl),
+            #[cfg(feature = "parquet")]
+            "parquet" => Ok(InputFormat::Columnar(ColumnarFormat::Parquet)),
+            #[cfg(feature = "parquet")]
+            "arrow" => Ok(InputFormat::Columnar(ColumnarFormat::ArrowIpc)),
+            #[cfg(not(feature = "parquet"))]
+            "parquet" | "arrow" => Err(format!("{format} input requires the parquet feature")),
+            _ => Err(format!(
+                "expected csv, jsonl, parquet or arrow, found {format}"
+            )),
+        }
+    }
+}
+
+/// Reads transactions from a file one row at a time, in any of the supported input formats.
+pub enum TransactionReader {
+    Csv {
+        reader: csv::Reader<Box<dyn Read>>,
+        headers: csv::ByteRecord,
+        record: csv::ByteRecord,
+        // Where the amount column is, if there is one.
+        amount_column: Option<usize>,
+    },
+    Jsonl {
+        reader: BufReader<Box<dyn Read>>,
+        line: String,
+        // How many lines have been read so far.
+        lines: u64,
+    },
+    #[cfg(feature = "parquet")]
+    Columnar(BatchReader),
+}
+
+impl TransactionReader {
+    /// Opens the file at the provided path, exiting if it cannot be read. Csv and jsonl files are
+    /// decompressed if they are gzip or zstd compressed.
+    pub fn open<P: AsRef<Path>>(path: P, format: InputFormat, strict: bool) -> TransactionReader {
+        #[cfg(feature = "parquet")]
+        if let InputFormat::Columnar(format) = format {
+            return TransactionReader::Columnar(BatchReader::open(path.as_ref(), format));
+        }
+
+        compression::open(path.as_ref())
+            .map_err(|e| e.to_string())
+            .and_then(|file| TransactionReader::from_reader(file, format, strict))
+            .or_else::<String, _>(|e| {
+                error!("Failed to read {}: {e}", path.as_ref().display());
+                process::exit(1)
+            })
+            .unwrap()
+    }
+
+    /// Reads csv or jsonl transactions from the given reader. In strict mode, csv fields aren't
+    /// trimmed and the header must name the expected columns exactly.
+    pub fn from_reader(
+        reader: Box<dyn Read>,
+        format: InputFormat,
+        strict: bool,
+    ) -> Result<TransactionReader, String> {
+        match format {
+            InputFormat::Csv => {
+                let mut reader = csv::ReaderBuilder::new()
+                    .trim(if strict {
+                        csv::Trim::None
+                    } else {
+                        csv::Trim::All
+                    })
+                    .from_reader(reader);
+                let headers = reader
+                    .byte_headers()
+                    .map_err(|e| format!("failed to read headers: {e}"))?
+                    .clone();
+                if strict {
+                    strict::check_header(&headers)
+                        .map_err(|e| format!("invalid header on line 1: {e}"))?;
+                }
+                let amount_column = headers.iter().position(|header| header == b"amount");
+                Ok(TransactionReader::Csv {
+                    reader,
+                    headers,
+                    record: csv::ByteRecord::new(),
+                    amount_column,
+                })
+            }
+            InputFormat::Jsonl => Ok(TransactionReader::Jsonl {
+                reader: BufReader::new(reader),
+                line: String::new(),
+                lines: 0,
+            }),
+            #[cfg(feature = "parquet")]
+            InputFormat::Columnar(format) => {
+                Err(format!("{format} input can only be read from files"))
+            }
+        }
+    }
+
+    /// Reads the next row, along with the line it starts on, returning `None` at the end of the
+    /// input. Rows that cannot be deserialized are returned as errors, while failing to read the
+    /// input at all is fatal. Columnar files have no lines, so rows are numbered instead.
+    pub fn next_row(&mut self) -> Option<(u64, Result<Transaction, String>)> {
+        match self {
+            TransactionReader::Csv {
+                reader,
+                headers,
+                record,
+                amount_column,
+            } => {
+                let more = match reader.read_byte_record(record) {
+                    Ok(more) => more,
+                    // A row with the wrong number of fields can be skipped like any other bad row.
+                    Err(e) if matches!(e.kind(), csv::ErrorKind::UnequalLengths { .. }) => {
+                        let line = e.position().map_or(0, csv::Position::line);
+                        return Some((line, Err(describe_csv_error(&e))));
+                    }
+                    Err(e) => {
+                        error!("Failed to read row from csv: {e}");
+                        process::exit(1)
+                    }
+                };
+                more.then(|| {
+                    let line = record.position().map_or(0, csv::Position::line);
+                    (
+                        line,
+                        record
+                            .deserialize(Some(headers))
+                            .map_err(|e| describe_csv_error(&e))
+                            .and_then(|transaction| {
+                                exact_amount(transaction, record, *amount_column)
+                            }),
+                    )
+                })
+            }
+            TransactionReader::Jsonl {
+                reader,
+                line,
+                lines,
+            } => loop {
+                line.clear();
+                let read = reader
+                    .read_line(line)
+                    .or_else::<std::io::Error, _>(|e| {
+                        error!("Failed to read line from jsonl: {e}");
+                        process::exit(1)
+                    })
+                    .unwrap();
+                if read == 0 {
+                    return None;
+                }
+                *lines += 1;
+                // Blank lines, such as a trailing newline, aren't rows.
+                if !line.trim().is_empty() {
+                    return Some((
+                        *lines,
+                        serde_json::from_str(line).map_err(|e| e.to_string()),
+                    ));
+                }
+            },
+            #[cfg(feature = "parquet")]
+            TransactionReader::Columnar(reader) => reader.next_row(),
+        }
+    }
+
+    /// Gives the position of the named column, starting from 1, if the input has columns.
+    pub fn column(&self, name: &str) -> Option<u64> {
+        match self {
+            TransactionReader::Csv { headers, .. } => headers
+                .iter()
+                .position(|header| header == name.as_bytes())
+                .map(|index| index as u64 + 1),
+            TransactionReader::Jsonl { .. } => None,
+            #[cfg(feature = "parquet")]
+            TransactionReader::Columnar(_) => None,
+        }
+    }
+}
+
+/// Parses a csv row's amount again from its text. The csv deserializer offers numeric fields as
+/// floats, which can't hold every amount exactly and accept exponents, so the amount it gives is
+/// only trusted to be present.
+fn exact_amount(
+    mut transaction: Transaction,
+    record: &csv::ByteRecord,
+    amount_column: Option<usize>,
+) -> Result<Transaction, String> {
+    let (Some(_), Some(column)) = (transaction.amount, amount_column) else {
+        return Ok(transaction);
+    };
+    let field = record.get(column).unwrap_or_default();
+    let amount = str::from_utf8(field)
+        .map_err(|e| e.to_string())
+        .and_then(str::parse)
+        .map_err(|e| format!("column {}: {e}", column + 1))?;
+    transaction.amount = Some(amount);
+    Ok(transaction)
+}
+
+/// Describes an error deserializing a csv row, giving the column it was found in, if known.
+fn describe_csv_error(e: &csv::Error) -> String {
+    match e.kind() {
+        csv::ErrorKind::Deserialize { err, .. } => match err.field() {
+            Some(field) => format!("column {}: {}", field + 1, err.kind()),
+            None => err.kind().to_string(),
+        },
+        csv::ErrorKind::UnequalLengths {
+            expected_len, len, ..
+        } => format!("expected {expected_len} fields, found {len}"),
+        _ => e.to_string(),
+    }
+}
+
+/// Where a row of input was read from.
+#[derive(Debug, Clone)]
+pub struct Location {
+    pub path: Rc<Path>,
+    // The line the row starts on, or for columnar files, the row's position in the file.
+    pub line: u64,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} line {}", self.path.display(), self.line)
+    }
+}
+
+/// Reads transactions from several files in turn, as though they were a single file. Each file is
+/// opened once the previous one has been read to the end.
+pub struct TransactionStream {
+    paths: vec::IntoIter<PathBuf>,
+    format: InputFormat,
+    strict: bool,
+    current: Option<(Rc<Path>, TransactionReader)>,
+}
+
+impl TransactionStream {
+    pub fn new(paths: Vec<PathBuf>, format: InputFormat, strict: bool) -> TransactionStream {
+        TransactionStream {
+            paths: paths.into_iter(),
+            format,
+            strict,
+            current: None,
+        }
+    }
+
+    /// Reads the next row, along with where it was read from, returning `None` once every file
+    /// has been read.
+    pub fn next_row(&mut self) -> Option<(Location, Result<Transaction, String>)> {
+        loop {
+            if let Some((path, reader)) = &mut self.current {
+                if let Some((line, row)) = reader.next_row() {
+                    let path = Rc::clone(path);
+                    return Some((Location { path, line }, row));
+                }
+            }
+            let path = self.paths.next()?;
+            let reader = TransactionReader::open(&path, self.format, self.strict);
+            self.current = Some((Rc::from(path), reader));
+        }
+    }
+
+    /// Gives the position of the named column in the file currently being read, if it has columns.
+    pub fn column(&self, name: &str) -> Option<u64> {
+        self.current.as_ref()?.1.column(name)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use crate::input::{InputFormat, TransactionReader};
+    use crate::transaction::Amount;
+
+    #[test]
+    fn exact_csv_amounts() {
+        let csv = "type, client, tx, amount\n\
+                   deposit, 1, 1, 1234567890123.4567\n\
+                   deposit, 1, 2, 1e3\n\
+                   dispute, 1, 1,\n";
+        let mut reader =
+            TransactionReader::from_reader(Box::new(Cursor::new(csv)), InputFormat::Csv, false)
+                .unwrap();
+        let mut amounts = Vec::new();
+        while let Some((_, row)) = reader.next_row() {
+            amounts.push(row.map(|transaction| transaction.amount));
+        }
+
+        assert_eq!(amounts[0], Ok(Some(Amount(12_345_678_901_234_567))));
+        assert_eq!(
+            amounts[1],
+            Err("column 4: 1e3 is not a non-negative decimal amount".to_string())
+        );
+        assert_eq!(amounts[2], Ok(None));
+    }
+}
@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use serde::{Serialize, Serializer};
+
+use crate::transaction::Amount;
+
+/// An account in the double-entry ledger. Each client has an available, pending and held account,
+/// while the platform has a clearing account for funds entering and leaving the system, and a loss
+/// account for chargebacks that could not be covered by the client's held funds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LedgerAccount {
+    Available(u16),
+    Pending(u16),
+    Held(u16),
+    Clearing,
+    ChargebackLoss,
+}
+
+impl LedgerAccount {
+    /// Returns the client the ledger account belongs to, if any.
+    pub fn client(self) -> Option<u16> {
+        match self {
+            LedgerAccount::Available(client)
+            | LedgerAccount::Pending(client)
+            | LedgerAccount::Held(client) => Some(client),
+            LedgerAccount::Clearing | LedgerAccount::ChargebackLoss => None,
+        }
+    }
+}
+
+impl fmt::Display for LedgerAccount {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LedgerAccount::Available(client) => write!(f, "client:{client}:available"),
+            LedgerAccount::Pending(client) => write!(f, "client:{client}:pending"),
+            LedgerAccount::Held(client) => write!(f, "client:{client}:held"),
+            LedgerAccount::Clearing => write!(f, "platform:clearing"),
+            LedgerAccount::ChargebackLoss => write!(f, "platform:chargeback_loss"),
+        }
+    }
+}
+
+impl Serialize for LedgerAccount {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// A journal entry moving an amount from one ledger account to another. Since the same amount
+/// leaves one account and enters another, every entry is balanced.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct JournalEntry {
+    pub tx: u32,
+    pub from: LedgerAccount,
+    pub to: LedgerAccount,
+    pub amount: Amount,
+}
+
+/// The balance of every ledger account posted to, along with an append-only journal of the
+/// entries posted if it is being kept. Balances are signed, as platform accounts go negative when
+/// funds enter the system.
+#[derive(Default)]
+pub struct Ledger {
+    // Empty unless the ledger was created to keep its journal.
+    pub journal: Vec<JournalEntry>,
+    keep_journal: bool,
+    balances: HashMap<LedgerAccount, i128>,
+}
+
+impl Ledger {
+    /// Creates a ledger that keeps every entry posted to it in its journal, rather than only the
+    /// balances they add up to.
+    pub fn with_journal() -> Ledger {
+        Ledger {
+            keep_journal: true,
+            ..Ledger::default()
+        }
+    }
+
+    pub fn post(&mut self, entry: JournalEntry) {
+        *self.balances.entry(entry.from).or_default() -= i128::from(entry.amount.0);
+        *self.balances.entry(entry.to).or_default() += i128::from(entry.amount.0);
+        if self.keep_journal {
+            self.journal.push(entry);
+        }
+    }
+
+    pub fn balance(&self, account: LedgerAccount) -> i128 {
+        self.balances.get(&account).copied().unwrap_or_default()
+    }
+
+    /// Returns the balance of a client's ledger account. Client accounts never go negative, since
+    /// funds are only ever moved out of them if they are there, and never exceed the largest
+    /// amount, since the engine rejects transactions that would take them past it.
+    pub fn client_balance(&self, account: LedgerAccount) -> Amount {
+        Amount(u64::try_from(self.balance(account)).expect("client ledger balance is out of range"))
+    }
+
+    /// Returns an error holding the sum of all balances, if it is not zero.
+    pub fn check(&self) -> Result<(), i128> {
+        let sum: i128 = self.balances.values().sum();
+        if sum == 0 {
+            Ok(())
+        } else {
+            Err(sum)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ledger::{JournalEntry, Ledger, LedgerAccount};
+    use crate::transaction::Amount;
+
+    #[test]
+    fn ledger_balances() {
+        let mut ledger = Ledger::with_journal();
+        ledger.post(JournalEntry {
+            tx: 1,
+            from: LedgerAccount::Clearing,
+            to: LedgerAccount::Available(1),
+            amount: Amount(10_000),
+        });
+        ledger.post(JournalEntry {
+            tx: 1,
+            from: LedgerAccount::Available(1),
+            to: LedgerAccount::Held(1),
+            amount: Amount(4_000),
+        });
+
+        assert_eq!(
+            ledger.client_balance(LedgerAccount::Available(1)),
+            Amount(6_000)
+        );
+        assert_eq!(ledger.client_balance(LedgerAccount::Held(1)), Amount(4_000));
+        assert_eq!(ledger.balance(LedgerAccount::Clearing), -10_000);
+        assert_eq!(ledger.check(), Ok(()));
+        assert_eq!(ledger.journal.len(), 2);
+    }
+
+    #[test]
+    fn ledger_without_journal() {
+        let mut ledger = Ledger::default();
+        ledger.post(JournalEntry {
+            tx: 1,
+            from: LedgerAccount::Clearing,
+            to: LedgerAccount::Available(1),
+            amount: Amount(10_000),
+        });
+
+        assert_eq!(
+            ledger.client_balance(LedgerAccount::Available(1)),
+            Amount(10_000)
+        );
+        assert!(ledger.journal.is_empty());
+    }
+}
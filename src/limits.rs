@@ -0,0 +1,192 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+
+use serde::Deserialize;
+
+use crate::transaction::Amount;
+
+/// Limits on a client's withdrawals, read from the `[limits]` table of a policy file. Clients may
+/// be put in a tier, and may have limits of their own. Each limit a client doesn't have comes from
+/// their tier, and failing that from the default limits.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct WithdrawalLimits {
+    // Length of the rolling window that withdrawal volume and count are measured over, in seconds.
+    pub window: u64,
+    pub default: Limits,
+    pub tiers: HashMap<String, Limits>,
+    pub clients: Vec<ClientLimits>,
+}
+
+/// Limits on withdrawals. Limits that aren't set don't apply.
+#[derive(Deserialize, Default, Debug, Clone, Copy, PartialEq)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Limits {
+    // Largest single withdrawal.
+    pub amount: Option<Amount>,
+    // Largest total withdrawn within the window.
+    pub volume: Option<Amount>,
+    // Most withdrawals within the window.
+    pub count: Option<u64>,
+}
+
+/// A client's tier, and any limits of their own.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct ClientLimits {
+    pub client: u16,
+    pub tier: Option<String>,
+    #[serde(flatten)]
+    pub limits: Limits,
+}
+
+/// The limit a withdrawal would breach.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Limit {
+    Amount,
+    Volume,
+    Count,
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let limit = match self {
+            Limit::Amount => "amount",
+            Limit::Volume => "volume",
+            Limit::Count => "count",
+        };
+        write!(f, "{limit}")
+    }
+}
+
+impl Default for WithdrawalLimits {
+    fn default() -> WithdrawalLimits {
+        WithdrawalLimits {
+            window: 86_400,
+            default: Limits::default(),
+            tiers: HashMap::new(),
+            clients: Vec::new(),
+        }
+    }
+}
+
+impl WithdrawalLimits {
+    /// Returns the limits that apply to the given client.
+    pub fn for_client(&self, client: u16) -> Limits {
+        let Some(own) = self.clients.iter().find(|limits| limits.client == client) else {
+            return self.default;
+        };
+        let tier = own
+            .tier
+            .as_ref()
+            .and_then(|tier| self.tiers.get(tier))
+            .map_or(self.default, |tier| tier.or(self.default));
+        own.limits.or(tier)
+    }
+
+    /// Returns the first tier given to a client that isn't defined, if any.
+    pub fn undefined_tier(&self) -> Option<&str> {
+        self.clients
+            .iter()
+            .filter_map(|limits| limits.tier.as_deref())
+            .find(|tier| !self.tiers.contains_key(*tier))
+    }
+}
+
+impl Limits {
+    /// Fills in the limits that aren't set from the fallback.
+    fn or(self, fallback: Limits) -> Limits {
+        Limits {
+            amount: self.amount.or(fallback.amount),
+            volume: self.volume.or(fallback.volume),
+            count: self.count.or(fallback.count),
+        }
+    }
+}
+
+/// Recent withdrawals of each client, oldest first, for checking withdrawals against rolling
+/// window limits.
+#[derive(Default)]
+pub struct RecentWithdrawals {
+    clients: HashMap<u16, VecDeque<(u64, Amount)>>,
+}
+
+impl RecentWithdrawals {
+    /// Checks a withdrawal against the client's limits. Volume and count are measured over the
+    /// window ending now, so they only apply when the engine's clock is running.
+    pub fn check(
+        &mut self,
+        client: u16,
+        amount: Amount,
+        limits: Limits,
+        window: u64,
+        now: Option<u64>,
+    ) -> Result<(), Limit> {
+        if limits.amount.is_some_and(|max| amount > max) {
+            return Err(Limit::Amount);
+        }
+        let Some(now) = now else {
+            return Ok(());
+        };
+
+        let recent = self.clients.entry(client).or_default();
+        while recent
+            .front()
+            .is_some_and(|(at, _)| now.saturating_sub(*at) >= window)
+        {
+            recent.pop_front();
+        }
+        if limits.count.is_some_and(|max| recent.len() as u64 >= max) {
+            return Err(Limit::Count);
+        }
+        let volume = recent
+            .iter()
+            .fold(amount, |volume, (_, amount)| volume + *amount);
+        if limits.volume.is_some_and(|max| volume > max) {
+            return Err(Limit::Volume);
+        }
+        Ok(())
+    }
+
+    /// Records a withdrawal that has been applied.
+    pub fn insert(&mut self, client: u16, amount: Amount, now: Option<u64>) {
+        if let Some(now) = now {
+            self.clients
+                .entry(client)
+                .or_default()
+                .push_back((now, amount));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::limits::{Limit, Limits, RecentWithdrawals};
+    use crate::transaction::Amount;
+
+    #[test]
+    fn rolling_window() {
+        let limits = Limits {
+            amount: Some(Amount(50)),
+            volume: Some(Amount(100)),
+            count: Some(3),
+        };
+        let mut recent = RecentWithdrawals::default();
+        let mut withdraw = |amount, now| -> Result<(), Limit> {
+            recent.check(1, Amount(amount), limits, 10, now)?;
+            recent.insert(1, Amount(amount), now);
+            Ok(())
+        };
+
+        assert_eq!(withdraw(60, Some(0)), Err(Limit::Amount));
+        assert_eq!(withdraw(50, Some(0)), Ok(()));
+        assert_eq!(withdraw(40, Some(1)), Ok(()));
+        assert_eq!(withdraw(20, Some(2)), Err(Limit::Volume));
+        assert_eq!(withdraw(10, Some(2)), Ok(()));
+        assert_eq!(withdraw(1, Some(9)), Err(Limit::Count));
+        // The first withdrawal has left the window.
+        assert_eq!(withdraw(1, Some(10)), Ok(()));
+        // Without timestamps, only the amount limit applies.
+        assert_eq!(withdraw(50, None), Ok(()));
+    }
+}
@@ -0,0 +1,45 @@
+use std::io;
+use std::process;
+use std::str::FromStr;
+
+use tracing_subscriber::EnvFilter;
+
+/// Formats that logs can be written to stderr in.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum LogFormat {
+    // Human-readable lines.
+    #[default]
+    Text,
+    // One JSON object per event, with its fields and those of the spans it happened in.
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<LogFormat, String> {
+        match format {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("expected text or json, found {format}")),
+        }
+    }
+}
+
+/// Starts writing logs to stderr in the given format, exiting if the filter is invalid. The
+/// filter is either a level, such as `debug`, or a list of directives in the `RUST_LOG` style.
+pub fn init(filter: &str, format: LogFormat) {
+    let filter = EnvFilter::try_new(filter)
+        .or_else::<tracing_subscriber::filter::ParseError, _>(|e| {
+            eprintln!("Invalid log level {filter}: {e}");
+            process::exit(1)
+        })
+        .unwrap();
+    let subscriber = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(io::stderr);
+    match format {
+        LogFormat::Text => subscriber.with_target(false).init(),
+        LogFormat::Json => subscriber.json().with_current_span(true).init(),
+    }
+}
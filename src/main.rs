@@ -6,303 +6,574 @@
 #![allow(clippy::cast_precision_loss)]
 
 mod account;
+mod admin;
+mod audit;
+#[cfg(feature = "parquet")]
+mod columnar;
+mod compression;
+mod config;
+mod diff;
+mod engine;
+mod expiry;
+mod explain;
+mod history;
+mod hold;
+mod input;
+mod ledger;
+mod limits;
+mod logging;
+mod metrics;
+mod output;
+mod policy;
+mod reconciliation;
+mod rejection;
+mod reorder;
+mod replay;
+mod rules;
+mod serve;
+mod signals;
+mod statement;
+mod strict;
+mod summary;
 mod transaction;
 
-use std::cmp::min;
-use std::fs::File;
+use std::env;
 use std::io;
 use std::path::Path;
 use std::process;
-use std::{collections::HashMap, env};
+use std::rc::Rc;
+use std::time::Instant;
 
-use csv::Reader;
+use serde::Serialize;
+use tracing::span::EnteredSpan;
+use tracing::{debug, debug_span, error, info, info_span, warn};
 
 use account::Account;
-use transaction::{Amount, Transaction, TxType};
+use audit::{AuditLog, AuditRecord, Outcome};
+use config::{Command, Config};
+use engine::Engine;
+use explain::Step;
+use input::{Location, TransactionStream};
+use output::{write_accounts, write_output, OutputFormat};
+use reconciliation::Reconciliation;
+use reorder::ReorderBuffer;
+use replay::ReplayPoint;
+use statement::statement;
+use summary::{format_amount, Summary};
+use transaction::Transaction;
 
 fn main() {
-    // Get file path from argument.
-    let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        eprintln!("Expected one argument, but received {}", args.len() - 1);
-        process::exit(1);
+    // Get command, file paths and options from arguments.
+    let (command, transactions_paths, mut config) =
+        Config::from_args(env::args().skip(1)).unwrap_or_else(|e| e.exit());
+    logging::init(&config.log_level, config.log_format);
+
+    // Validating is a dry run, so it writes no audit log.
+    if matches!(command, Command::Validate { .. }) {
+        config.audit_log = None;
+    }
+
+    // Serving takes transactions over HTTP rather than from files, until the process is stopped.
+    if let Command::Serve { listen } = &command {
+        serve::serve(listen, &config);
+        return;
+    }
+
+    // Diffing compares account files rather than processing transactions.
+    if let Command::Diff {
+        old,
+        new,
+        accounts_format,
+    } = &command
+    {
+        diff(old, new, *accounts_format, config.output_format);
+        return;
     }
-    let transactions_path = &args[1];
 
     // Determine account balances from transactions.
-    let accounts = process_transactions(transactions_path);
+    let started = Instant::now();
+    let (engine, summary) = process_transactions(&transactions_paths, &config);
+    metrics::log_summary(&engine, started.elapsed());
 
-    // Write account details to standard output.
-    let mut wtr = csv::Writer::from_writer(io::stdout());
-    for account in accounts.values() {
-        wtr.serialize(account)
-            .or_else::<csv::Error, _>(|e| {
-                eprintln!("Failed to write account details to stdout: {}", e);
-                process::exit(1)
-            })
-            .unwrap();
+    // Write admin actions to the admin log, if one was requested, before anything can fail, so
+    // that it covers everything applied, as the audit log does. Validating is a dry run, so it
+    // writes no admin log.
+    if let Some(admin_log_path) = &config.admin_log {
+        if !matches!(command, Command::Validate { .. }) {
+            write_records(admin_log_path, &engine.admin_log, "admin log");
+        }
     }
-    wtr.flush()
-        .or_else::<csv::Error, _>(|e| {
-            eprintln!("Failed to flush output to stdout: {}", e);
-            process::exit(1)
-        })
-        .unwrap();
-}
 
-/// Reads csv from provided path, and returns account balances resulting from the described
-/// transactions.
-#[allow(clippy::blocks_in_if_conditions)]
-fn process_transactions<P: AsRef<Path>>(path: P) -> HashMap<u16, Account> {
-    // Prepare csv reader.
-    let mut transactions_reader = csv::ReaderBuilder::new()
-        .trim(csv::Trim::All)
-        .from_path(path)
-        .or_else::<csv::Error, _>(|e| {
-            eprintln!("Failed to read csv: {}", e);
-            process::exit(1)
-        })
-        .unwrap();
+    // In strict mode, reading stops at the first invalid row.
+    if summary.invalid {
+        process::exit(1);
+    }
 
-    let mut accounts: HashMap<u16, Account> = HashMap::new();
-    let mut raw_record = csv::ByteRecord::new();
-    let headers = transactions_reader
-        .byte_headers()
-        .or_else::<csv::Error, _>(|e| {
-            eprintln!("Failed to read headers from csv: {}", e);
-            process::exit(1)
-        })
-        .unwrap()
-        .clone();
-    let mut disputed_transactions: HashMap<u32, Transaction> = HashMap::new();
+    // Every journal entry is balanced, so the ledger as a whole must balance too.
+    if let Err(sum) = engine.ledger.check() {
+        error!("Ledger does not balance: balances sum to {sum}");
+        process::exit(1);
+    }
 
-    // Read csv line by line, updating account balances as we go.
-    while transactions_reader
-        .read_byte_record(&mut raw_record)
-        .or_else::<csv::Error, _>(|e| {
-            eprintln!("Failed to read row from csv: {}", e);
-            process::exit(1)
-        })
-        .unwrap()
-    {
-        let transaction: Transaction = match raw_record.deserialize(Some(&headers)) {
-            Err(e) => {
-                eprintln!("Failed to deserialize transaction from csv: {}", e);
-                continue;
-            }
-            Ok(tx) => tx,
-        };
+    // Funds moved into and out of client accounts must account for their closing balances. The
+    // control totals are written out first, if requested, so that a failure can be looked into.
+    // Validating writes them too, since checking them is part of checking the input.
+    let totals = Reconciliation::new(&engine);
+    if let Some(reconciliation_path) = &config.reconciliation {
+        write_records(reconciliation_path, &[totals], "reconciliation");
+    }
+    if !totals.balanced {
+        error!(
+            opening = %format_amount(totals.opening),
+            deposits = %format_amount(totals.deposits),
+            withdrawals = %format_amount(totals.withdrawals),
+            chargebacks = %format_amount(totals.chargebacks),
+            closing = %format_amount(totals.closing),
+            "Control totals do not reconcile with closing balances"
+        );
+        process::exit(1);
+    }
 
-        match transaction.tx_type {
-            TxType::Deposit => deposit(&mut accounts, &transaction),
-            TxType::Withdrawal => withdrawal(&mut accounts, &transaction),
-            TxType::Dispute => dispute(
-                &mut accounts,
-                &transaction,
-                &mut disputed_transactions,
-                &mut transactions_reader,
-            ),
-            TxType::Resolve => resolve(&mut accounts, &transaction, &mut disputed_transactions),
-            TxType::Chargeback => {
-                chargeback(&mut accounts, &transaction, &mut disputed_transactions);
-            }
+    match command {
+        Command::Process => write_accounts(
+            &engine.client_accounts(None),
+            config.output_format,
+            io::stdout(),
+        ),
+        Command::Replay { client } => write_accounts(
+            &engine.client_accounts(client),
+            config.output_format,
+            io::stdout(),
+        ),
+        Command::Explain { .. } => write_output(
+            &summary.steps,
+            config.output_format,
+            "explanation",
+            io::stdout(),
+        ),
+        Command::Statement { client } => write_output(
+            &statement(&engine.history, client),
+            config.output_format,
+            "statement",
+            io::stdout(),
+        ),
+        // Validating is a dry run, so nothing else but the control totals is written.
+        Command::Validate { max_rejection_rate } => {
+            validate(&summary, &totals, max_rejection_rate);
+            return;
+        }
+        Command::Serve { .. } | Command::Diff { .. } => {
+            unreachable!("serving and diffing don't process transactions")
         }
     }
 
-    accounts
-}
-
-/// Adds specified amount to available account balance.
-fn deposit(accounts: &mut HashMap<u16, Account>, transaction: &Transaction) {
-    let account = accounts
-        .entry(transaction.client)
-        .or_insert_with(|| Account::new(transaction.client));
+    // Write the ledger's journal entries, if requested.
+    if let Some(journal_path) = &config.journal {
+        write_records(journal_path, &engine.ledger.journal, "journal");
+    }
 
-    account.available += transaction.amount.unwrap_or_default();
-    account.total += transaction.amount.unwrap_or_default();
+    // Write the flags raised by rules and fraud signals, if requested.
+    if let Some(alerts_path) = &config.alerts {
+        write_records(alerts_path, &engine.flags, "alerts");
+    }
 }
 
-/// Reduces available account balance by specified amount.
-fn withdrawal(accounts: &mut HashMap<u16, Account>, transaction: &Transaction) {
-    let account = accounts
-        .entry(transaction.client)
-        .or_insert_with(|| Account::new(transaction.client));
-
-    // If an overflow occurs (account balance is insufficient), we ignore the withdrawal.
-    if let Some(available) = account
-        .available
-        .checked_sub(transaction.amount.unwrap_or_default())
-    {
-        account.available = available;
-        account.total -= transaction.amount.unwrap_or_default();
-    } else {
-        eprintln!(
-            "Failed to withdraw from account of client {} due to insufficient funds.",
-            account.client
+/// Writes the summary to standard output, exiting with an error if the fraction of rows that were
+/// unreadable or rejected is above the maximum.
+fn validate(summary: &Summary, totals: &Reconciliation, max_rejection_rate: f64) {
+    summary
+        .write(totals, io::stdout().lock())
+        .or_else::<io::Error, _>(|e| {
+            error!("Failed to write summary to stdout: {e}");
+            process::exit(1)
+        })
+        .unwrap();
+    if summary.rejection_rate() > max_rejection_rate {
+        error!(
+            "Rejection rate of {:.2}% is above the maximum of {:.2}%",
+            summary.rejection_rate() * 100.0,
+            max_rejection_rate * 100.0
         );
+        process::exit(1);
+    }
+}
+
+/// Writes the differences between two account files to standard output, exiting with an error if
+/// there are any, or if either file cannot be read.
+fn diff(old: &Path, new: &Path, accounts_format: OutputFormat, output_format: OutputFormat) {
+    let read = |path: &Path| {
+        diff::read_accounts(path, accounts_format)
+            .or_else::<String, _>(|e| {
+                error!("Failed to read accounts from {}: {e}", path.display());
+                process::exit(1)
+            })
+            .unwrap()
     };
+    let differences = diff::diff_accounts(&read(old), &read(new));
+    write_output(&differences, output_format, "differences", io::stdout());
+    if !differences.is_empty() {
+        process::exit(1);
+    }
 }
 
-/// Disputes specified transaction, if it exists.
-fn dispute(
-    accounts: &mut HashMap<u16, Account>,
-    transaction: &Transaction,
-    disputed_transactions: &mut HashMap<u32, Transaction>,
-    transactions_reader: &mut Reader<File>,
-) {
-    // Seek back start so we can search for the disputed tx.
-    let position = transactions_reader.position().clone();
-    transactions_reader
-        .seek(csv::Position::new())
+/// Writes records to a csv file at the provided path, exiting if this fails.
+fn write_records<T: Serialize>(path: &Path, records: &[T], name: &str) {
+    let mut wtr = csv::Writer::from_path(path)
         .or_else::<csv::Error, _>(|e| {
-            eprintln!("Failed to seek to beginning of csv: {}", e);
+            error!("Failed to create {name}: {e}");
             process::exit(1)
         })
         .unwrap();
-
-    // Read past header row.
-    let mut raw_record = csv::ByteRecord::new();
-    transactions_reader
-        .read_byte_record(&mut raw_record)
+    for record in records {
+        wtr.serialize(record)
+            .or_else::<csv::Error, _>(|e| {
+                error!("Failed to write to {name}: {e}");
+                process::exit(1)
+            })
+            .unwrap();
+    }
+    wtr.flush()
         .or_else::<csv::Error, _>(|e| {
-            eprintln!("Failed to read csv headers: {}", e);
+            error!("Failed to flush {name}: {e}");
             process::exit(1)
         })
         .unwrap();
+}
 
-    // Prepare iterator.
-    let mut tx_iter = transactions_reader.deserialize().map(|tx_or_err| {
-        tx_or_err
-            .or_else::<csv::Error, _>(|e| {
-                eprintln!("Failed to read row from csv as Transaction: {}", e);
-                process::exit(1)
-            })
-            .unwrap()
-    });
-
-    // If the disputed transaction doesn't exist, we do nothing.
-    if let Some(disputed_tx) = tx_iter.find(|tx: &Transaction| tx.id == transaction.id) {
-        let account = accounts
-            .entry(transaction.client)
-            .or_insert_with(|| Account::new(transaction.client));
+/// Enters the span for the file a row was read from, unless it's already entered.
+fn enter_file_span(file_span: &mut Option<(Rc<Path>, EnteredSpan)>, location: &Location) {
+    if file_span
+        .as_ref()
+        .is_none_or(|(path, _)| *path != location.path)
+    {
+        // The previous file's span is exited before the next one is entered.
+        drop(file_span.take());
+        let span = info_span!("file", path = %location.path.display()).entered();
+        info!("Processing file");
+        *file_span = Some((location.path.clone(), span));
+    }
+}
 
-        // Don't allow disputing someone else's transaction.
-        if transaction.client != disputed_tx.client {
-            eprintln!("Dispute must be initiated by owner of disputed transaction");
-            return;
+/// Checks a row read in strict mode, logging an error, recording the row in the audit log if there
+/// is one, and returning false if it is invalid. An invalid transaction is recorded as rejected,
+/// leaving the client's balances as they were.
+fn check_row(
+    transactions: &TransactionStream,
+    engine: &Engine,
+    audit_log: &mut Option<AuditLog>,
+    location: &Location,
+    row: &Result<Transaction, String>,
+) -> bool {
+    let transaction = match row {
+        Ok(transaction) => transaction,
+        Err(e) => {
+            error!(file = %location.path.display(), line = location.line, reason = %e, "Invalid transaction");
+            if let Some(audit_log) = audit_log {
+                audit_log.write(&AuditRecord::unreadable(location, e.clone()));
+            }
+            return false;
         }
+    };
+    let Err((field, e)) = strict::check_fields(transaction) else {
+        return true;
+    };
+    let reason = match transactions.column(field) {
+        Some(column) => format!("column {column}: {e}"),
+        None => e,
+    };
+    error!(file = %location.path.display(), line = location.line, %reason, "Invalid transaction");
+    if let Some(audit_log) = audit_log {
+        let account = engine
+            .accounts
+            .get(&transaction.client)
+            .copied()
+            .unwrap_or_else(|| Account::new(transaction.client));
+        audit_log.write(&AuditRecord::transaction(
+            location,
+            transaction,
+            Outcome::Rejected,
+            Some(reason),
+            account,
+            account,
+        ));
+    }
+    false
+}
 
-        // Only allow disputing Deposits.
-        if disputed_tx.tx_type != TxType::Deposit {
-            eprintln!("Only deposits can be disputed");
-            return;
+/// Reads transactions from provided paths in order, as a single stream, and returns the engine
+/// holding account balances resulting from the described transactions, along with a summary of
+/// the rows read. In strict mode, reading stops at the first invalid row, and the summary says so;
+/// the audit log still records that row, and is flushed.
+fn process_transactions<P: AsRef<Path>>(paths: &[P], config: &Config) -> (Engine, Summary) {
+    let paths = paths
+        .iter()
+        .map(|path| path.as_ref().to_path_buf())
+        .collect();
+    let mut transactions = TransactionStream::new(paths, config.input_format, config.strict);
+    let mut engine = config.new_engine();
+    let mut reorder_buffer = config.reorder_window.map(ReorderBuffer::new);
+    let mut summary = Summary::default();
+    let mut audit_log = config
+        .audit_log
+        .as_ref()
+        .map(|path| AuditLog::create(path, config.audit_format));
+
+    // Read transactions row by row, updating account balances as we go. Everything logged while
+    // reading a file is within a span naming it.
+    let mut file_span: Option<(Rc<Path>, EnteredSpan)> = None;
+    let mut row = 0;
+    let mut replay_point_reached = false;
+    while !replay_point_reached {
+        let Some((location, next_row)) = transactions.next_row() else {
+            break;
+        };
+        enter_file_span(&mut file_span, &location);
+        row += 1;
+        if let Some(ReplayPoint::Row(last_row)) = config.until {
+            if row > last_row {
+                break;
+            }
+        }
+        summary.rows += 1;
+        let valid = !config.strict
+            || check_row(&transactions, &engine, &mut audit_log, &location, &next_row);
+        if !valid {
+            summary.invalid = true;
+            break;
         }
+        let transaction = match next_row {
+            Err(e) => {
+                warn!(file = %location.path.display(), line = location.line, reason = %e, "Failed to deserialize transaction");
+                summary.unreadable += 1;
+                engine.metrics.unreadable += 1;
+                if let Some(audit_log) = &mut audit_log {
+                    audit_log.write(&AuditRecord::unreadable(&location, e));
+                }
+                continue;
+            }
+            Ok(tx) => tx,
+        };
 
-        // If the disputed amount is more than the available balance, the best we can do is hold the
-        // available balance. Ideally in the real world, this should rarely happen because withdrawals
-        // should be disallowed for a suitable holding period.
-        account.held += min(account.available, disputed_tx.amount.unwrap_or_default());
-        account.available = account
-            .available
-            .saturating_sub(disputed_tx.amount.unwrap_or_default());
+        summary.read(&transaction);
 
-        disputed_transactions.insert(disputed_tx.id, disputed_tx);
-    } else {
-        eprintln!("Failed to dispute transaction: transaction does not exist");
+        // When reordering, only apply transactions once they have left the reorder window.
+        if let Some(buffer) = &mut reorder_buffer {
+            buffer.push(location, transaction);
+            while let Some((location, transaction)) = buffer.pop_ready() {
+                replay_point_reached = !apply_transaction(
+                    &mut engine,
+                    &mut summary,
+                    &mut audit_log,
+                    &location,
+                    &transaction,
+                    config,
+                );
+                if replay_point_reached {
+                    break;
+                }
+            }
+        } else {
+            replay_point_reached = !apply_transaction(
+                &mut engine,
+                &mut summary,
+                &mut audit_log,
+                &location,
+                &transaction,
+                config,
+            );
+        }
     }
 
-    // Return to current row.
-    transactions_reader
-        .seek(position)
-        .expect("failed to seek back to current row of csv");
-}
+    // Apply whatever is left in the reorder window, unless reading stopped at an invalid row.
+    if let Some(buffer) = reorder_buffer.as_mut().filter(|_| !summary.invalid) {
+        while !replay_point_reached {
+            let Some((location, transaction)) = buffer.pop() else {
+                break;
+            };
+            replay_point_reached = !apply_transaction(
+                &mut engine,
+                &mut summary,
+                &mut audit_log,
+                &location,
+                &transaction,
+                config,
+            );
+        }
+    }
 
-/// Resolves disputed transaction, if it exists.
-fn resolve(
-    accounts: &mut HashMap<u16, Account>,
-    transaction: &Transaction,
-    disputed_transactions: &mut HashMap<u32, Transaction>,
-) {
-    // If transaction is not disputed, do nothing.
-    if disputed_transactions.remove(&transaction.id).is_some() {
-        let account = accounts
-            .entry(transaction.client)
-            .or_insert_with(|| Account::new(transaction.client));
-
-        // Get the total remaining amount disputed for the given account.
-        let amount_disputed = disputed_transactions.values().fold(Amount(0), |acc, tx| {
-            if tx.client == transaction.client {
-                acc + tx.amount.unwrap_or_default()
-            } else {
-                Amount(0)
-            }
-        });
+    // Balances at a point in time include any deposits that had matured, or disputes that had
+    // expired, by then.
+    if let Some(ReplayPoint::Time(time)) = config.until {
+        engine.advance_to(time);
+    }
 
-        // Set held equal to the amount disputed, unless total balance is smaller.
-        account.held = min(amount_disputed, account.total);
-        account.available = account.total.saturating_sub(account.held);
-    } else {
-        eprintln!("Failed to resolve dispute: transaction is not disputed");
+    if let Some(audit_log) = &mut audit_log {
+        audit_log.flush();
     }
+    (engine, summary)
 }
 
-/// Charges back disputed transaction, if it exists.
-fn chargeback(
-    accounts: &mut HashMap<u16, Account>,
+/// Applies a transaction to the engine, logging it along with whether it was rejected or flagged,
+/// counting it in the summary if it is rejected, and recording it in the audit log if there is
+/// one, and in the summary if its client is being explained. Returns false if processing should
+/// stop here, because the replay point has been reached.
+fn apply_transaction(
+    engine: &mut Engine,
+    summary: &mut Summary,
+    audit_log: &mut Option<AuditLog>,
+    location: &Location,
     transaction: &Transaction,
-    disputed_transactions: &mut HashMap<u32, Transaction>,
-) {
-    // If transaction is not disputed, do nothing.
-    if let Some(disputed_tx) = disputed_transactions.remove(&transaction.id) {
-        let account = accounts
-            .entry(transaction.client)
-            .or_insert_with(|| Account::new(transaction.client));
-
-        // Reduce amount held by amount charged back.
-        account.held = account
-            .held
-            .saturating_sub(disputed_tx.amount.unwrap_or_default());
-
-        // Recalculate total.
-        account.total = account.held + account.available;
-
-        // Lock account.
-        account.locked = true;
-    } else {
-        eprintln!("Failed to chargeback dispute: transaction is not disputed");
+    config: &Config,
+) -> bool {
+    if config
+        .until
+        .is_some_and(|point| point.excludes(transaction.timestamp))
+    {
+        return false;
+    }
+
+    // Everything logged while applying the transaction, by the engine or here, is within a span
+    // naming it.
+    let _span = debug_span!(
+        "transaction",
+        r#type = ?transaction.tx_type,
+        client = transaction.client,
+        tx = transaction.id
+    )
+    .entered();
+    let flagged = engine.flags.len();
+    let balances = |engine: &Engine| {
+        engine
+            .accounts
+            .get(&transaction.client)
+            .copied()
+            .unwrap_or_else(|| Account::new(transaction.client))
+    };
+    let before = balances(engine);
+    let result = engine.apply(transaction);
+    if let Some(audit_log) = audit_log {
+        let (outcome, reason) = match result {
+            Ok(()) => (Outcome::Applied, None),
+            Err(e) => (Outcome::Rejected, Some(e.to_string())),
+        };
+        audit_log.write(&AuditRecord::transaction(
+            location,
+            transaction,
+            outcome,
+            reason,
+            before,
+            balances(engine),
+        ));
     }
+    if config.explain == Some(transaction.client) {
+        let flags: Vec<_> = engine.flags[flagged..]
+            .iter()
+            .map(|flag| flag.code)
+            .collect();
+        summary.steps.push(Step::new(
+            location,
+            transaction,
+            result,
+            &flags,
+            balances(engine),
+        ));
+    }
+    match result {
+        Ok(()) => debug!(
+            file = %location.path.display(),
+            line = location.line,
+            r#type = ?transaction.tx_type,
+            client = transaction.client,
+            tx = transaction.id,
+            timestamp = transaction.timestamp,
+            "Applied transaction"
+        ),
+        Err(e) => {
+            summary.reject(e);
+            warn!(
+                file = %location.path.display(),
+                line = location.line,
+                r#type = ?transaction.tx_type,
+                client = transaction.client,
+                tx = transaction.id,
+                timestamp = transaction.timestamp,
+                reason = %e,
+                "Rejected transaction"
+            );
+        }
+    }
+    for flag in &engine.flags[flagged..] {
+        warn!(
+            file = %location.path.display(),
+            line = location.line,
+            r#type = ?transaction.tx_type,
+            client = transaction.client,
+            tx = transaction.id,
+            timestamp = transaction.timestamp,
+            code = flag.code,
+            "Flagged transaction"
+        );
+    }
+
+    !config
+        .until
+        .is_some_and(|point| point.ends_at(transaction.id))
 }
 
+// The original tests write long amounts without separators.
 #[cfg(test)]
+#[allow(clippy::unreadable_literal)]
 mod test {
     use std::{collections::HashMap, path::Path};
 
+    use crate::account::Account;
+    use crate::admin::AdminAction;
+    use crate::audit::{AuditFormat, Outcome};
+    use crate::config::{Command, Config};
+    use crate::diff::{self, Change};
+    use crate::engine::Engine;
+    use crate::expiry::{DisputeWindows, ExpiryAction};
+    use crate::hold::HoldPeriods;
+    use crate::input::InputFormat;
+    use crate::ledger::LedgerAccount;
+    use crate::limits::Limit;
+    use crate::output::OutputFormat;
+    use crate::policy::{DuplicateIds, Policy};
+    use crate::process_transactions;
+    use crate::reconciliation::Reconciliation;
+    use crate::rejection::Rejection;
+    use crate::replay::ReplayPoint;
+    use crate::signals::SignalSettings;
+    use crate::statement::statement;
     use crate::transaction::{Amount, Transaction, TxType};
-    use crate::Account;
-    use crate::{deposit, process_transactions, withdrawal};
 
     #[test]
     fn deposit_success() {
-        let mut accounts: HashMap<u16, Account> = HashMap::new();
+        let mut engine = Engine::new();
         let transaction = Transaction {
             tx_type: TxType::Deposit,
             client: 1,
             id: 1,
             amount: Some(Amount::from(12345.67891)),
+            operator: None,
+            reason: None,
+            timestamp: None,
         };
 
-        deposit(&mut accounts, &transaction);
+        engine.deposit(&transaction).unwrap();
 
         assert_eq!(
-            accounts,
+            engine.accounts,
             HashMap::from([(
                 1,
                 Account {
                     client: 1,
                     available: Amount(123456789),
+                    pending: Amount(0),
                     held: Amount(0),
                     total: Amount(123456789),
                     locked: false,
+                    closed: false,
                 }
             )])
         );
@@ -310,33 +581,41 @@ mod test {
 
     #[test]
     fn withdrawal_success() {
-        let mut accounts: HashMap<u16, Account> = HashMap::new();
+        let mut engine = Engine::new();
         let deposit_transaction = Transaction {
             tx_type: TxType::Deposit,
             client: 1,
             id: 1,
             amount: Some(Amount::from(12345.67891)),
+            operator: None,
+            reason: None,
+            timestamp: None,
         };
         let withdrawal_transaction = Transaction {
             tx_type: TxType::Withdrawal,
             client: 1,
             id: 2,
             amount: Some(Amount::from(2345.97891)),
+            operator: None,
+            reason: None,
+            timestamp: None,
         };
 
-        deposit(&mut accounts, &deposit_transaction);
-        withdrawal(&mut accounts, &withdrawal_transaction);
+        engine.deposit(&deposit_transaction).unwrap();
+        engine.withdrawal(&withdrawal_transaction).unwrap();
 
         assert_eq!(
-            accounts,
+            engine.accounts,
             HashMap::from([(
                 1,
                 Account {
                     client: 1,
                     available: Amount(99997000),
+                    pending: Amount(0),
                     held: Amount(0),
                     total: Amount(99997000),
                     locked: false,
+                    closed: false,
                 }
             )])
         );
@@ -344,33 +623,44 @@ mod test {
 
     #[test]
     fn withdrawal_failure() {
-        let mut accounts: HashMap<u16, Account> = HashMap::new();
+        let mut engine = Engine::new();
         let deposit_transaction = Transaction {
             tx_type: TxType::Deposit,
             client: 1,
             id: 1,
             amount: Some(Amount::from(12345.67891)),
+            operator: None,
+            reason: None,
+            timestamp: None,
         };
         let withdrawal_transaction = Transaction {
             tx_type: TxType::Withdrawal,
             client: 1,
             id: 2,
             amount: Some(Amount::from(12345.67901)),
+            operator: None,
+            reason: None,
+            timestamp: None,
         };
 
-        deposit(&mut accounts, &deposit_transaction);
-        withdrawal(&mut accounts, &withdrawal_transaction);
+        engine.deposit(&deposit_transaction).unwrap();
+        assert_eq!(
+            engine.withdrawal(&withdrawal_transaction),
+            Err(Rejection::InsufficientFunds)
+        );
 
         assert_eq!(
-            accounts,
+            engine.accounts,
             HashMap::from([(
                 1,
                 Account {
                     client: 1,
                     available: Amount(123456789),
+                    pending: Amount(0),
                     held: Amount(0),
                     total: Amount(123456789),
                     locked: false,
+                    closed: false,
                 }
             )])
         );
@@ -378,37 +668,327 @@ mod test {
 
     #[test]
     fn dispute_available() {
-        let accounts = process_transactions(Path::new("test/data/dispute_available.csv"));
+        let (engine, _) = process_transactions(
+            &[Path::new("test/data/dispute_available.csv")],
+            &Config::default(),
+        );
 
         assert_eq!(
-            accounts,
+            engine.accounts,
             HashMap::from([(
                 1,
                 Account {
                     client: 1,
                     available: Amount(0),
+                    pending: Amount(0),
                     held: Amount(123456789),
                     total: Amount(123456789),
                     locked: false,
+                    closed: false,
+                }
+            )])
+        );
+    }
+
+    #[test]
+    fn compressed_input() {
+        for path in [
+            "test/data/dispute_available.csv.gz",
+            "test/data/dispute_available.csv.zst",
+        ] {
+            let (engine, _) = process_transactions(&[Path::new(path)], &Config::default());
+
+            assert_eq!(
+                engine.accounts,
+                HashMap::from([(
+                    1,
+                    Account {
+                        client: 1,
+                        available: Amount(0),
+                        pending: Amount(0),
+                        held: Amount(123_456_789),
+                        total: Amount(123_456_789),
+                        locked: false,
+                        closed: false,
+                    }
+                )])
+            );
+        }
+    }
+
+    #[test]
+    fn config_file() {
+        let args = |args: &[&str]| args.iter().map(ToString::to_string).collect::<Vec<_>>();
+
+        // Without a command, transactions are processed.
+        let (command, paths, config) = Config::from_args(args(&[
+            "--config=test/data/config.toml",
+            "test/data/dispute_available.csv",
+        ]))
+        .unwrap();
+        assert_eq!(command, Command::Process);
+        assert_eq!(paths, vec![Path::new("test/data/dispute_available.csv")]);
+        assert_eq!(config.output_format, OutputFormat::Json);
+        assert_eq!(config.hold_periods.for_client(1), Some(60));
+        assert_eq!(config.hold_periods.for_client(2), Some(0));
+        assert_eq!(config.dispute_windows.on_expiry, ExpiryAction::Chargeback);
+
+        // Options given on the command line take precedence over the config file.
+        let (command, _, config) = Config::from_args(args(&[
+            "statement",
+            "--client=2",
+            "--config=test/data/config.toml",
+            "--output-format=jsonl",
+            "test/data/dispute_available.csv",
+        ]))
+        .unwrap();
+        assert_eq!(command, Command::Statement { client: Some(2) });
+        assert_eq!(config.output_format, OutputFormat::Jsonl);
+        assert_eq!(config.hold_periods.for_client(1), Some(60));
+
+        assert!(Config::from_args(args(&["replay", "test/data/dispute_available.csv"])).is_err());
+        assert!(Config::from_args(args(&[
+            "--hold-period=soon",
+            "test/data/dispute_available.csv"
+        ]))
+        .is_err());
+    }
+
+    #[test]
+    fn explain() {
+        let (command, paths, config) = Config::from_args(
+            [
+                "explain",
+                "--client=1",
+                "--policy=test/data/policy.toml",
+                "test/data/policy.csv",
+            ]
+            .map(String::from),
+        )
+        .unwrap();
+        assert_eq!(command, Command::Explain { client: 1 });
+        let (_, summary) = process_transactions(&paths, &config);
+
+        // Only the client's rows are explained, each with the balances after it.
+        let steps: Vec<_> = summary
+            .steps
+            .iter()
+            .map(|step| (step.line, step.outcome, step.reason.as_deref(), step.held))
+            .collect();
+        assert_eq!(
+            steps,
+            vec![
+                (2, Outcome::Applied, None, Amount(0)),
+                (3, Outcome::Applied, None, Amount(0)),
+                (
+                    4,
+                    Outcome::Rejected,
+                    Some("transaction id has already been used"),
+                    Amount(0)
+                ),
+                (5, Outcome::Applied, None, Amount(40_000)),
+                (6, Outcome::Applied, None, Amount(0)),
+                (10, Outcome::Applied, None, Amount(0)),
+                (11, Outcome::Applied, None, Amount(10_000)),
+                (12, Outcome::Applied, None, Amount(0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn diff() {
+        let (command, _, config) = Config::from_args(
+            [
+                "diff",
+                "--output-format=jsonl",
+                "test/data/accounts_old.csv",
+                "test/data/accounts_new.csv",
+            ]
+            .map(String::from),
+        )
+        .unwrap();
+        let Command::Diff {
+            old,
+            new,
+            accounts_format,
+        } = command
+        else {
+            panic!("expected the diff command, found {command:?}");
+        };
+        assert_eq!(accounts_format, OutputFormat::Csv);
+        assert_eq!(config.output_format, OutputFormat::Jsonl);
+
+        // Rows are matched by client, missing columns from older files take their defaults, and
+        // amounts are compared exactly however they are written.
+        let old = diff::read_accounts(&old, accounts_format).unwrap();
+        let new = diff::read_accounts(&new, accounts_format).unwrap();
+        let differences: Vec<_> = diff::diff_accounts(&old, &new)
+            .iter()
+            .map(|difference| (difference.client, difference.change, difference.field))
+            .collect();
+        assert_eq!(
+            differences,
+            vec![
+                (1, Change::Changed, Some("available")),
+                (1, Change::Changed, Some("total")),
+                (1, Change::Changed, Some("locked")),
+                (3, Change::Removed, None),
+                (4, Change::Added, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn multiple_files() {
+        let (_, paths, config) = Config::from_args(["test/data/partitioned".to_string()]).unwrap();
+        assert_eq!(
+            paths,
+            vec![
+                Path::new("test/data/partitioned/2024-01-01T00.csv"),
+                Path::new("test/data/partitioned/2024-01-01T01.csv"),
+            ]
+        );
+        let (_, glob_paths, _) =
+            Config::from_args(["test/data/partitioned/*.csv".to_string()]).unwrap();
+        assert_eq!(glob_paths, paths);
+
+        // Neither an empty directory nor a pattern matching nothing is taken as empty input.
+        let empty = std::env::temp_dir().join("payments-engine-empty");
+        std::fs::create_dir_all(&empty).unwrap();
+        assert!(Config::from_args([empty.display().to_string()]).is_err());
+        assert!(Config::from_args(["test/data/partitioned/*.jsonl".to_string()]).is_err());
+
+        let (engine, _) = process_transactions(&paths, &config);
+
+        assert_eq!(
+            engine.accounts,
+            HashMap::from([
+                (
+                    1,
+                    Account {
+                        client: 1,
+                        available: Amount(0),
+                        pending: Amount(0),
+                        held: Amount(50_000),
+                        total: Amount(50_000),
+                        locked: false,
+                        closed: false,
+                    }
+                ),
+                (
+                    2,
+                    Account {
+                        client: 2,
+                        available: Amount(20_000),
+                        pending: Amount(0),
+                        held: Amount(0),
+                        total: Amount(20_000),
+                        locked: false,
+                        closed: false,
+                    }
+                ),
+            ])
+        );
+    }
+
+    #[test]
+    fn strict() {
+        let config = Config {
+            strict: true,
+            ..Config::default()
+        };
+        let (engine, _) = process_transactions(&[Path::new("test/data/strict.csv")], &config);
+
+        assert_eq!(
+            engine.accounts,
+            HashMap::from([(
+                1,
+                Account {
+                    client: 1,
+                    available: Amount(0),
+                    pending: Amount(0),
+                    held: Amount(15_000),
+                    total: Amount(15_000),
+                    locked: false,
+                    closed: false,
                 }
             )])
         );
     }
 
+    #[test]
+    fn strict_invalid_row() {
+        let audit_path = std::env::temp_dir().join("payments-engine-strict-audit.csv");
+        let config = Config {
+            strict: true,
+            audit_log: Some(audit_path.clone()),
+            ..Config::default()
+        };
+        let (engine, summary) =
+            process_transactions(&[Path::new("test/data/strict_invalid.csv")], &config);
+
+        // Reading stops at the deposit without an amount, which is audited as rejected along with
+        // the rows before it.
+        assert!(summary.invalid);
+        assert_eq!(engine.accounts[&1].available, Amount(30_000));
+        let outcomes: Vec<String> = csv::Reader::from_path(&audit_path)
+            .unwrap()
+            .records()
+            .map(|record| record.unwrap()[7].to_string())
+            .collect();
+        assert_eq!(outcomes, ["applied", "applied", "rejected"]);
+    }
+
+    #[test]
+    fn strict_lock_amount() {
+        let config = Config {
+            strict: true,
+            ..Config::default()
+        };
+        let (engine, summary) =
+            process_transactions(&[Path::new("test/data/strict_lock_amount.csv")], &config);
+
+        // A lock with an amount is invalid, so the account is never locked.
+        assert!(summary.invalid);
+        assert_eq!(engine.accounts[&1].available, Amount(20_000));
+        assert!(!engine.accounts[&1].locked);
+    }
+
+    #[test]
+    fn validate_summary() {
+        let (_, summary) =
+            process_transactions(&[Path::new("test/data/lock.csv")], &Config::default());
+
+        assert_eq!(summary.rows, 4);
+        assert_eq!(summary.unreadable, 0);
+        assert_eq!(summary.by_type[&TxType::Deposit], 2);
+        assert_eq!(
+            summary.rejections,
+            HashMap::from([(Rejection::AccountLocked, 2)])
+        );
+        assert_eq!(summary.clients.len(), 1);
+        assert!((summary.rejection_rate() - 0.5).abs() < f64::EPSILON);
+    }
+
     #[test]
     fn dispute_unavailable() {
-        let accounts = process_transactions(Path::new("test/data/dispute_unavailable.csv"));
+        let (engine, _) = process_transactions(
+            &[Path::new("test/data/dispute_unavailable.csv")],
+            &Config::default(),
+        );
 
         assert_eq!(
-            accounts,
+            engine.accounts,
             HashMap::from([(
                 1,
                 Account {
                     client: 1,
                     available: Amount(0),
+                    pending: Amount(0),
                     held: Amount(99997000),
                     total: Amount(99997000),
                     locked: false,
+                    closed: false,
                 }
             )])
         );
@@ -416,18 +996,23 @@ mod test {
 
     #[test]
     fn resolve_available() {
-        let accounts = process_transactions(Path::new("test/data/resolve_available.csv"));
+        let (engine, _) = process_transactions(
+            &[Path::new("test/data/resolve_available.csv")],
+            &Config::default(),
+        );
 
         assert_eq!(
-            accounts,
+            engine.accounts,
             HashMap::from([(
                 1,
                 Account {
                     client: 1,
                     available: Amount(123456789),
+                    pending: Amount(0),
                     held: Amount(0),
                     total: Amount(123456789),
                     locked: false,
+                    closed: false,
                 }
             )])
         );
@@ -435,18 +1020,23 @@ mod test {
 
     #[test]
     fn resolve_unavailable() {
-        let accounts = process_transactions(Path::new("test/data/resolve_unavailable.csv"));
+        let (engine, _) = process_transactions(
+            &[Path::new("test/data/resolve_unavailable.csv")],
+            &Config::default(),
+        );
 
         assert_eq!(
-            accounts,
+            engine.accounts,
             HashMap::from([(
                 1,
                 Account {
                     client: 1,
                     available: Amount(123446789),
+                    pending: Amount(0),
                     held: Amount(0),
                     total: Amount(123446789),
                     locked: false,
+                    closed: false,
                 }
             )])
         );
@@ -454,18 +1044,23 @@ mod test {
 
     #[test]
     fn chargeback_available() {
-        let accounts = process_transactions(Path::new("test/data/chargeback_available.csv"));
+        let (engine, _) = process_transactions(
+            &[Path::new("test/data/chargeback_available.csv")],
+            &Config::default(),
+        );
 
         assert_eq!(
-            accounts,
+            engine.accounts,
             HashMap::from([(
                 1,
                 Account {
                     client: 1,
                     available: Amount(10000),
+                    pending: Amount(0),
                     held: Amount(0),
                     total: Amount(10000),
                     locked: true,
+                    closed: false,
                 }
             )])
         );
@@ -473,23 +1068,782 @@ mod test {
 
     #[test]
     fn chargeback_unavailable() {
-        let accounts = process_transactions(Path::new("test/data/chargeback_unavailable.csv"));
+        let (engine, _) = process_transactions(
+            &[Path::new("test/data/chargeback_unavailable.csv")],
+            &Config::default(),
+        );
 
         assert_eq!(
-            accounts,
+            engine.accounts,
             HashMap::from([(
                 1,
                 Account {
                     client: 1,
                     available: Amount(0),
+                    pending: Amount(0),
                     held: Amount(0),
                     total: Amount(0),
                     locked: true,
+                    closed: false,
+                }
+            )])
+        );
+        assert_eq!(
+            engine.ledger.balance(LedgerAccount::ChargebackLoss),
+            -10_000
+        );
+        assert_eq!(engine.ledger.check(), Ok(()));
+    }
+
+    #[test]
+    fn balance_overflow() {
+        let (engine, summary) = process_transactions(
+            &[Path::new("test/data/balance_overflow.csv")],
+            &Config::default(),
+        );
+
+        // The second deposit would take the balance past the largest amount, so it is rejected
+        // rather than overflowing, while smaller deposits still fit.
+        assert_eq!(
+            engine.accounts[&1].total,
+            Amount(10_000_000_000_000_000_000 + 10_000)
+        );
+        assert_eq!(
+            summary.rejections,
+            HashMap::from([(Rejection::BalanceOverflow, 1)])
+        );
+        assert_eq!(engine.ledger.check(), Ok(()));
+    }
+
+    #[test]
+    fn dispute_not_owner() {
+        let (engine, summary) = process_transactions(
+            &[Path::new("test/data/dispute_not_owner.csv")],
+            &Config::default(),
+        );
+
+        // Client 2 can't dispute, resolve or charge back client 1's deposit, and doesn't get an
+        // account for trying.
+        assert_eq!(
+            engine.accounts,
+            HashMap::from([(
+                1,
+                Account {
+                    client: 1,
+                    available: Amount(0),
+                    pending: Amount(0),
+                    held: Amount(100_000),
+                    total: Amount(100_000),
+                    locked: false,
+                    closed: false,
+                }
+            )])
+        );
+        assert_eq!(engine.open_disputes(), 1);
+        assert_eq!(summary.rejections.get(&Rejection::NotOwner), Some(&3));
+    }
+
+    #[test]
+    fn resolve_other_disputes() {
+        let (engine, _) = process_transactions(
+            &[Path::new("test/data/resolve_other_disputes.csv")],
+            &Config::default(),
+        );
+
+        // Resolving one of client 1's disputes leaves their other disputes held, however client
+        // 2's disputes are interleaved with them.
+        assert_eq!(
+            engine.accounts.get(&1),
+            Some(&Account {
+                client: 1,
+                available: Amount(10_000),
+                pending: Amount(0),
+                held: Amount(350_000),
+                total: Amount(360_000),
+                locked: false,
+                closed: false,
+            })
+        );
+        assert_eq!(
+            engine.accounts.get(&2).map(|account| account.held),
+            Some(Amount(1_000_000))
+        );
+    }
+
+    #[test]
+    fn lock() {
+        let (engine, _) =
+            process_transactions(&[Path::new("test/data/lock.csv")], &Config::default());
+
+        assert_eq!(
+            engine.accounts,
+            HashMap::from([(
+                1,
+                Account {
+                    client: 1,
+                    available: Amount(100_000),
+                    pending: Amount(0),
+                    held: Amount(0),
+                    total: Amount(100_000),
+                    locked: true,
+                    closed: false,
+                }
+            )])
+        );
+        assert_eq!(
+            engine.admin_log,
+            vec![AdminAction {
+                tx_type: TxType::Lock,
+                client: 1,
+                id: 2,
+                operator: 7,
+                reason: "suspected fraud".to_string(),
+                timestamp: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn unlock_chargeback() {
+        let (engine, _) = process_transactions(
+            &[Path::new("test/data/unlock_chargeback.csv")],
+            &Config::default(),
+        );
+
+        assert_eq!(
+            engine.accounts,
+            HashMap::from([(
+                1,
+                Account {
+                    client: 1,
+                    available: Amount(60_000),
+                    pending: Amount(0),
+                    held: Amount(0),
+                    total: Amount(60_000),
+                    locked: false,
+                    closed: false,
                 }
             )])
         );
     }
 
+    #[test]
+    fn close() {
+        let (engine, _) =
+            process_transactions(&[Path::new("test/data/close.csv")], &Config::default());
+
+        assert_eq!(
+            engine.accounts,
+            HashMap::from([
+                (
+                    1,
+                    Account {
+                        client: 1,
+                        available: Amount(0),
+                        pending: Amount(0),
+                        held: Amount(0),
+                        total: Amount(0),
+                        locked: false,
+                        closed: true,
+                    }
+                ),
+                (
+                    2,
+                    Account {
+                        client: 2,
+                        available: Amount(100_000),
+                        pending: Amount(0),
+                        held: Amount(0),
+                        total: Amount(100_000),
+                        locked: false,
+                        closed: false,
+                    }
+                )
+            ])
+        );
+        assert_eq!(engine.admin_log.len(), 1);
+    }
+
+    #[test]
+    fn timestamps_out_of_order() {
+        let (engine, _) =
+            process_transactions(&[Path::new("test/data/timestamps.csv")], &Config::default());
+
+        assert_eq!(
+            engine.accounts,
+            HashMap::from([(
+                1,
+                Account {
+                    client: 1,
+                    available: Amount(100_000),
+                    pending: Amount(0),
+                    held: Amount(0),
+                    total: Amount(100_000),
+                    locked: false,
+                    closed: false,
+                }
+            )])
+        );
+    }
+
+    #[test]
+    fn timestamps_reordered() {
+        let config = Config {
+            reorder_window: Some(10),
+            ..Config::default()
+        };
+        let (engine, _) = process_transactions(&[Path::new("test/data/timestamps.csv")], &config);
+
+        assert_eq!(
+            engine.accounts,
+            HashMap::from([(
+                1,
+                Account {
+                    client: 1,
+                    available: Amount(30_000),
+                    pending: Amount(0),
+                    held: Amount(0),
+                    total: Amount(30_000),
+                    locked: false,
+                    closed: false,
+                }
+            )])
+        );
+    }
+
+    #[test]
+    fn jsonl_input() {
+        let config = Config {
+            input_format: InputFormat::Jsonl,
+            ..Config::default()
+        };
+        let (engine, _) = process_transactions(&[Path::new("test/data/jsonl.jsonl")], &config);
+
+        assert_eq!(
+            engine.accounts,
+            HashMap::from([
+                (
+                    1,
+                    Account {
+                        client: 1,
+                        available: Amount(0),
+                        pending: Amount(0),
+                        held: Amount(0),
+                        total: Amount(0),
+                        locked: false,
+                        closed: false,
+                    }
+                ),
+                (
+                    2,
+                    Account {
+                        client: 2,
+                        available: Amount(0),
+                        pending: Amount(0),
+                        held: Amount(100_001),
+                        total: Amount(100_001),
+                        locked: false,
+                        closed: false,
+                    }
+                ),
+            ])
+        );
+    }
+
+    #[test]
+    fn hold_period() {
+        let config = Config {
+            hold_periods: HoldPeriods {
+                default: Some(100),
+                clients: HashMap::from([(2, 1000)]),
+            },
+            ..Config::default()
+        };
+        let (engine, _) = process_transactions(&[Path::new("test/data/hold_period.csv")], &config);
+
+        assert_eq!(
+            engine.accounts,
+            HashMap::from([
+                (
+                    1,
+                    Account {
+                        client: 1,
+                        available: Amount(50_000),
+                        pending: Amount(0),
+                        held: Amount(40_000),
+                        total: Amount(90_000),
+                        locked: false,
+                        closed: false,
+                    }
+                ),
+                (
+                    2,
+                    Account {
+                        client: 2,
+                        available: Amount(0),
+                        pending: Amount(70_000),
+                        held: Amount(0),
+                        total: Amount(70_000),
+                        locked: false,
+                        closed: false,
+                    }
+                )
+            ])
+        );
+    }
+
+    #[test]
+    fn hold_period_rejected() {
+        let config = Config {
+            hold_periods: HoldPeriods {
+                default: Some(100),
+                clients: HashMap::new(),
+            },
+            ..Config::default()
+        };
+        let (engine, summary) =
+            process_transactions(&[Path::new("test/data/hold_period_rejected.csv")], &config);
+
+        // The withdrawal is rejected, but time has still moved on past the end of the deposit's
+        // holding period.
+        assert_eq!(
+            summary.rejections,
+            HashMap::from([(Rejection::InsufficientFunds, 1)])
+        );
+        assert_eq!(
+            engine
+                .accounts
+                .get(&1)
+                .map(|account| (account.available, account.pending)),
+            Some((Amount(100_000), Amount(0)))
+        );
+    }
+
+    #[test]
+    fn hold_period_dispute() {
+        let config = Config {
+            hold_periods: HoldPeriods {
+                default: Some(86_400),
+                clients: HashMap::new(),
+            },
+            ..Config::default()
+        };
+        let (engine, summary) =
+            process_transactions(&[Path::new("test/data/hold_period_dispute.csv")], &config);
+
+        // Resolving a dispute puts the deposit back on hold until it would have matured, so the
+        // first withdrawal is rejected and the second is applied.
+        assert_eq!(
+            engine.accounts,
+            HashMap::from([(
+                1,
+                Account {
+                    client: 1,
+                    available: Amount(600_000),
+                    pending: Amount(0),
+                    held: Amount(0),
+                    total: Amount(600_000),
+                    locked: false,
+                    closed: false,
+                }
+            )])
+        );
+        assert_eq!(
+            summary.rejections,
+            HashMap::from([(Rejection::InsufficientFunds, 1)])
+        );
+    }
+
+    #[test]
+    fn policy() {
+        let config = Config {
+            policy: Policy::load(Path::new("test/data/policy.toml")).unwrap(),
+            ..Config::default()
+        };
+        let (engine, summary) = process_transactions(&[Path::new("test/data/policy.csv")], &config);
+
+        // Client 1's charged back withdrawal is returned without locking the account, while
+        // client 2 can't dispute a deposit that has partly been withdrawn.
+        assert_eq!(
+            engine.accounts,
+            HashMap::from([
+                (
+                    1,
+                    Account {
+                        client: 1,
+                        available: Amount(90_000),
+                        pending: Amount(0),
+                        held: Amount(0),
+                        total: Amount(90_000),
+                        locked: false,
+                        closed: false,
+                    }
+                ),
+                (
+                    2,
+                    Account {
+                        client: 2,
+                        available: Amount(20_000),
+                        pending: Amount(0),
+                        held: Amount(0),
+                        total: Amount(20_000),
+                        locked: false,
+                        closed: false,
+                    }
+                ),
+            ])
+        );
+        assert_eq!(
+            summary.rejections,
+            HashMap::from([
+                (Rejection::DuplicateTransaction, 1),
+                (Rejection::InsufficientFunds, 1)
+            ])
+        );
+    }
+
+    #[test]
+    fn withdrawal_limits() {
+        let config = Config {
+            policy: Policy::load(Path::new("test/data/policy.toml")).unwrap(),
+            ..Config::default()
+        };
+        let (engine, summary) = process_transactions(&[Path::new("test/data/limits.csv")], &config);
+
+        assert_eq!(engine.accounts[&1].available, Amount(92_000_000));
+        assert_eq!(engine.accounts[&2].available, Amount(10_000_000));
+        assert_eq!(
+            summary.rejections,
+            HashMap::from([
+                (Rejection::WithdrawalLimit(Limit::Count), 1),
+                (Rejection::WithdrawalLimit(Limit::Amount), 1)
+            ])
+        );
+    }
+
+    #[test]
+    fn fraud_signals() {
+        let config = Config {
+            policy: Policy {
+                signals: SignalSettings {
+                    rapid_withdrawal: Some(60),
+                    repeated_disputes: Some(2),
+                    withdrawal_after_dispute: Some(3600),
+                },
+                ..Policy::default()
+            },
+            ..Config::default()
+        };
+        let (engine, summary) =
+            process_transactions(&[Path::new("test/data/signals.csv")], &config);
+
+        // Flagged transactions are still applied.
+        assert!(summary.rejections.is_empty());
+        assert_eq!(
+            engine
+                .flags
+                .iter()
+                .map(|flag| (flag.client, flag.tx, flag.code))
+                .collect::<Vec<_>>(),
+            vec![
+                (1, 2, "rapid-withdrawal"),
+                (2, 4, "repeated-disputes"),
+                (2, 5, "withdrawal-after-dispute"),
+            ]
+        );
+        let flags: Vec<(u32, String)> = statement(&engine.history, Some(1))
+            .into_iter()
+            .map(|line| (line.id, line.flags))
+            .collect();
+        assert_eq!(
+            flags,
+            vec![(1, String::new()), (2, "rapid-withdrawal".to_string())]
+        );
+    }
+
+    #[test]
+    fn rapid_withdrawal_without_timestamp() {
+        let config = Config {
+            policy: Policy {
+                signals: SignalSettings {
+                    rapid_withdrawal: Some(60),
+                    ..SignalSettings::default()
+                },
+                ..Policy::default()
+            },
+            ..Config::default()
+        };
+        let (engine, _) = process_transactions(
+            &[Path::new("test/data/signals_missing_timestamp.csv")],
+            &config,
+        );
+
+        // With only one of the timestamps, the withdrawal is flagged if it follows the deposit.
+        assert_eq!(
+            engine
+                .flags
+                .iter()
+                .map(|flag| (flag.client, flag.tx))
+                .collect::<Vec<_>>(),
+            vec![(1, 2), (2, 4)]
+        );
+    }
+
+    #[test]
+    fn audit_log() {
+        let audit_path = std::env::temp_dir().join("payments-engine-audit.jsonl");
+        let config = Config {
+            audit_log: Some(audit_path.clone()),
+            audit_format: AuditFormat::Jsonl,
+            ..Config::default()
+        };
+        process_transactions(&[Path::new("test/data/audit.csv")], &config);
+
+        let records: Vec<serde_json::Value> = std::fs::read_to_string(&audit_path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let summary: Vec<_> = records
+            .iter()
+            .map(|record| {
+                (
+                    record["line"].as_u64().unwrap(),
+                    record["outcome"].as_str().unwrap(),
+                    record["available_before"].as_f64(),
+                    record["available_after"].as_f64(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (2, "applied", Some(0.0), Some(2.0)),
+                (3, "rejected", Some(2.0), Some(2.0)),
+                (4, "unreadable", None, None),
+                (5, "applied", Some(2.0), Some(0.5)),
+            ]
+        );
+        assert_eq!(records[1]["reason"], "insufficient funds");
+    }
+
+    #[test]
+    fn reconciliation() {
+        let (engine, _) = process_transactions(
+            &[Path::new("test/data/chargeback_unavailable.csv")],
+            &Config::default(),
+        );
+        let totals = Reconciliation::new(&engine);
+
+        assert_eq!(totals.deposits, 123_466_789);
+        assert_eq!(totals.withdrawals, 20_000);
+        // The deposit of 12345.6789 is charged back, though only 12344.6789 was left to hold.
+        assert_eq!(totals.chargebacks, 123_446_789);
+        assert_eq!(totals.chargeback_losses, 10_000);
+        assert_eq!(totals.closing, 0);
+        assert!(totals.balanced);
+    }
+
+    #[test]
+    fn journal() {
+        let path = Path::new("test/data/chargeback_unavailable.csv");
+        let (engine, _) = process_transactions(&[path], &Config::default());
+        assert!(engine.ledger.journal.is_empty());
+
+        // The journal is only kept when it is to be written out.
+        let config = Config {
+            journal: Some("journal.csv".into()),
+            ..Config::default()
+        };
+        let (engine, _) = process_transactions(&[path], &config);
+        assert_eq!(engine.ledger.journal.len(), 6);
+        assert_eq!(engine.ledger.check(), Ok(()));
+    }
+
+    #[test]
+    fn dispute_windows_resolve() {
+        let config = Config {
+            dispute_windows: DisputeWindows {
+                max_deposit_age: Some(100),
+                deadline: Some(50),
+                on_expiry: ExpiryAction::Resolve,
+            },
+            ..Config::default()
+        };
+        let (engine, _) =
+            process_transactions(&[Path::new("test/data/dispute_windows.csv")], &config);
+
+        assert_eq!(
+            engine.accounts,
+            HashMap::from([(
+                1,
+                Account {
+                    client: 1,
+                    available: Amount(160_000),
+                    pending: Amount(0),
+                    held: Amount(0),
+                    total: Amount(160_000),
+                    locked: false,
+                    closed: false,
+                }
+            )])
+        );
+    }
+
+    #[test]
+    fn dispute_windows_chargeback() {
+        let config = Config {
+            dispute_windows: DisputeWindows {
+                max_deposit_age: Some(100),
+                deadline: Some(50),
+                on_expiry: ExpiryAction::Chargeback,
+            },
+            ..Config::default()
+        };
+        let (engine, _) =
+            process_transactions(&[Path::new("test/data/dispute_windows.csv")], &config);
+
+        assert_eq!(
+            engine.accounts,
+            HashMap::from([(
+                1,
+                Account {
+                    client: 1,
+                    available: Amount(100_000),
+                    pending: Amount(0),
+                    held: Amount(0),
+                    total: Amount(100_000),
+                    locked: true,
+                    closed: false,
+                }
+            )])
+        );
+    }
+
+    #[test]
+    fn dispute_window_closed() {
+        let config = Config {
+            dispute_windows: DisputeWindows {
+                max_deposit_age: Some(100),
+                ..DisputeWindows::default()
+            },
+            policy: Policy {
+                disputable: vec![TxType::Deposit, TxType::Withdrawal],
+                duplicate_ids: DuplicateIds::Reject,
+                ..Policy::default()
+            },
+            ..Config::default()
+        };
+        let (engine, summary) =
+            process_transactions(&[Path::new("test/data/dispute_window_closed.csv")], &config);
+
+        // Both transactions are too old to dispute by the time of the resolve, but the disputed
+        // withdrawal can still be resolved, and their ids are still known.
+        assert_eq!(engine.accounts[&1].available, Amount(70_000));
+        assert_eq!(engine.accounts[&1].held, Amount(0));
+        assert_eq!(
+            summary.rejections,
+            HashMap::from([
+                (Rejection::DisputeWindowClosed, 1),
+                (Rejection::DuplicateTransaction, 1)
+            ])
+        );
+        let totals = Reconciliation::new(&engine);
+        assert_eq!(totals.withdrawals, 40_000);
+        assert!(totals.balanced);
+
+        // When reused ids are applied, the ids of transactions too old to dispute aren't kept, so
+        // they are unknown to later disputes.
+        let config = Config {
+            policy: Policy {
+                duplicate_ids: DuplicateIds::Apply,
+                ..config.policy
+            },
+            ..config
+        };
+        let (engine, summary) =
+            process_transactions(&[Path::new("test/data/dispute_window_closed.csv")], &config);
+        assert_eq!(engine.accounts[&1].available, Amount(140_000));
+        assert_eq!(
+            summary.rejections,
+            HashMap::from([(Rejection::UnknownTransaction, 1)])
+        );
+    }
+
+    #[test]
+    fn statement_running_balances() {
+        let path = Path::new("test/data/resolve_unavailable.csv");
+        // The history is only kept for statements, or for rules that use it.
+        let (engine, _) = process_transactions(&[path], &Config::default());
+        assert!(engine.history.is_empty());
+
+        let config = Config {
+            statement: true,
+            ..Config::default()
+        };
+        let (engine, _) = process_transactions(&[path], &config);
+
+        let balances: Vec<(TxType, Amount, Amount)> = statement(&engine.history, Some(1))
+            .into_iter()
+            .map(|line| (line.tx_type, line.available, line.held))
+            .collect();
+        assert_eq!(
+            balances,
+            vec![
+                (TxType::Deposit, Amount(123_456_789), Amount(0)),
+                (TxType::Withdrawal, Amount(123_446_789), Amount(0)),
+                (TxType::Dispute, Amount(0), Amount(123_446_789)),
+                (TxType::Resolve, Amount(123_446_789), Amount(0)),
+            ]
+        );
+        assert!(statement(&engine.history, Some(2)).is_empty());
+    }
+
+    #[test]
+    fn replay_points() {
+        let balances_at = |until| {
+            let config = Config {
+                hold_periods: HoldPeriods {
+                    default: Some(100),
+                    clients: HashMap::new(),
+                },
+                until: Some(until),
+                ..Config::default()
+            };
+            let (engine, _) =
+                process_transactions(&[Path::new("test/data/hold_period.csv")], &config);
+            let account = engine.accounts[&1];
+            (account.available, account.pending)
+        };
+
+        assert_eq!(
+            balances_at(ReplayPoint::Row(3)),
+            (Amount(0), Amount(100_000))
+        );
+        assert_eq!(
+            balances_at(ReplayPoint::Transaction(4)),
+            (Amount(0), Amount(140_000))
+        );
+        assert_eq!(
+            balances_at(ReplayPoint::Time(200)),
+            (Amount(50_000), Amount(40_000))
+        );
+        assert_eq!(
+            balances_at(ReplayPoint::Time(260)),
+            (Amount(50_000), Amount(0))
+        );
+
+        // Rows without a timestamp are applied up to the first row timestamped after the replay
+        // point, but not after it.
+        let config = Config {
+            until: Some(ReplayPoint::Time(200)),
+            ..Config::default()
+        };
+        let (engine, _) =
+            process_transactions(&[Path::new("test/data/replay_untimestamped.csv")], &config);
+        assert_eq!(engine.accounts[&1].available, Amount(150_000));
+    }
+
     #[test]
     fn large_dataset() {
         let path = Path::new("test/data/large_dataset.csv");
@@ -503,6 +1857,9 @@ mod test {
                     client: 1,
                     id: i,
                     amount: Some(Amount(12345)),
+                    operator: None,
+                    reason: None,
+                    timestamp: None,
                 };
                 writer
                     .serialize(transaction)
@@ -510,18 +1867,23 @@ mod test {
             }
         }
 
-        let accounts = process_transactions(Path::new("test/data/large_dataset.csv"));
+        let (engine, _) = process_transactions(
+            &[Path::new("test/data/large_dataset.csv")],
+            &Config::default(),
+        );
 
         assert_eq!(
-            accounts,
+            engine.accounts,
             HashMap::from([(
                 1,
                 Account {
                     client: 1,
                     available: Amount(12345000000),
+                    pending: Amount(0),
                     held: Amount(0),
                     total: Amount(12345000000),
                     locked: false,
+                    closed: false,
                 }
             )])
         );
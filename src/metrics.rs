@@ -0,0 +1,288 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::time::Duration;
+
+use tracing::info;
+
+use crate::engine::Engine;
+use crate::transaction::TxType;
+
+/// Upper bounds of the buckets that processing latencies are counted in, in seconds.
+const LATENCY_BUCKETS: [f64; 10] = [
+    0.000_001, 0.000_005, 0.000_01, 0.000_05, 0.000_1, 0.000_5, 0.001, 0.005, 0.01, 0.05,
+];
+
+/// Counts of the transactions an engine has been asked to apply, and how long it took to apply
+/// them, by type.
+#[derive(Default, Debug)]
+pub struct Metrics {
+    pub by_type: BTreeMap<TxType, TypeMetrics>,
+    // Rows that couldn't be read as transactions, and so never reached the engine.
+    pub unreadable: u64,
+}
+
+/// Counts and processing latencies of the transactions of one type.
+#[derive(Default, Debug)]
+pub struct TypeMetrics {
+    pub applied: u64,
+    pub rejected: u64,
+    pub latency: Histogram,
+}
+
+/// A histogram of durations, counted in the latency buckets.
+#[derive(Default, Debug)]
+pub struct Histogram {
+    // Counts of the durations falling in each bucket, not including those in earlier buckets,
+    // followed by the count of durations above every bucket.
+    buckets: [u64; LATENCY_BUCKETS.len() + 1],
+    // Total of the durations, in seconds.
+    pub sum: f64,
+    pub count: u64,
+}
+
+impl Histogram {
+    pub fn observe(&mut self, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        let bucket = LATENCY_BUCKETS
+            .iter()
+            .position(|bound| seconds <= *bound)
+            .unwrap_or(LATENCY_BUCKETS.len());
+        self.buckets[bucket] += 1;
+        self.sum += seconds;
+        self.count += 1;
+    }
+
+    /// Returns the count of durations in each bucket or an earlier one, alongside the bucket's
+    /// upper bound.
+    fn cumulative(&self) -> impl Iterator<Item = (Option<f64>, u64)> + '_ {
+        LATENCY_BUCKETS
+            .iter()
+            .map(|bound| Some(*bound))
+            .chain([None])
+            .zip(self.buckets.iter().scan(0, |total, count| {
+                *total += count;
+                Some(*total)
+            }))
+    }
+
+    /// The mean duration, in seconds.
+    pub fn mean(&self) -> f64 {
+        if self.count == 0 {
+            return 0.0;
+        }
+        self.sum / self.count as f64
+    }
+}
+
+impl Metrics {
+    /// Counts a transaction that the engine applied, or rejected if `applied` is false, taking the
+    /// given time to do so.
+    pub fn record(&mut self, tx_type: TxType, applied: bool, latency: Duration) {
+        let metrics = self.by_type.entry(tx_type).or_default();
+        if applied {
+            metrics.applied += 1;
+        } else {
+            metrics.rejected += 1;
+        }
+        metrics.latency.observe(latency);
+    }
+
+    pub fn applied(&self) -> u64 {
+        self.by_type.values().map(|metrics| metrics.applied).sum()
+    }
+
+    pub fn rejected(&self) -> u64 {
+        self.by_type.values().map(|metrics| metrics.rejected).sum()
+    }
+}
+
+/// Renders the engine's metrics in the Prometheus text exposition format.
+pub fn exposition(engine: &Engine) -> String {
+    let metrics = &engine.metrics;
+    let mut out = String::new();
+
+    header(
+        &mut out,
+        "payments_transactions_total",
+        "counter",
+        "Transactions the engine was asked to apply, by type and outcome.",
+    );
+    for (tx_type, type_metrics) in &metrics.by_type {
+        let tx_type = type_label(*tx_type);
+        for (outcome, count) in [
+            ("applied", type_metrics.applied),
+            ("rejected", type_metrics.rejected),
+        ] {
+            writeln!(
+                out,
+                "payments_transactions_total{{type=\"{tx_type}\",outcome=\"{outcome}\"}} {count}"
+            )
+            .unwrap();
+        }
+    }
+
+    header(
+        &mut out,
+        "payments_unreadable_rows_total",
+        "counter",
+        "Rows that couldn't be read as transactions.",
+    );
+    writeln!(out, "payments_unreadable_rows_total {}", metrics.unreadable).unwrap();
+
+    header(
+        &mut out,
+        "payments_flags_total",
+        "counter",
+        "Applied transactions flagged by rules, by rule.",
+    );
+    let mut flags = BTreeMap::new();
+    for flag in &engine.flags {
+        *flags.entry(flag.code).or_insert(0) += 1;
+    }
+    for (code, count) in flags {
+        writeln!(out, "payments_flags_total{{code=\"{code}\"}} {count}").unwrap();
+    }
+
+    header(
+        &mut out,
+        "payments_processing_seconds",
+        "histogram",
+        "Time taken to apply or reject a transaction, by type.",
+    );
+    for (tx_type, type_metrics) in &metrics.by_type {
+        let tx_type = type_label(*tx_type);
+        let latency = &type_metrics.latency;
+        for (bound, count) in latency.cumulative() {
+            let bound = bound.map_or_else(|| "+Inf".to_string(), |bound| bound.to_string());
+            writeln!(
+                out,
+                "payments_processing_seconds_bucket{{type=\"{tx_type}\",le=\"{bound}\"}} {count}"
+            )
+            .unwrap();
+        }
+        writeln!(
+            out,
+            "payments_processing_seconds_sum{{type=\"{tx_type}\"}} {}",
+            latency.sum
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "payments_processing_seconds_count{{type=\"{tx_type}\"}} {}",
+            latency.count
+        )
+        .unwrap();
+    }
+
+    header(
+        &mut out,
+        "payments_open_disputes",
+        "gauge",
+        "Disputes that haven't been resolved or charged back.",
+    );
+    writeln!(out, "payments_open_disputes {}", engine.open_disputes()).unwrap();
+
+    header(
+        &mut out,
+        "payments_locked_accounts",
+        "gauge",
+        "Accounts that are locked.",
+    );
+    writeln!(out, "payments_locked_accounts {}", engine.locked_accounts()).unwrap();
+
+    header(&mut out, "payments_accounts", "gauge", "Client accounts.");
+    writeln!(out, "payments_accounts {}", engine.accounts.len()).unwrap();
+    out
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    writeln!(out, "# HELP {name} {help}").unwrap();
+    writeln!(out, "# TYPE {name} {kind}").unwrap();
+}
+
+fn type_label(tx_type: TxType) -> String {
+    format!("{tx_type:?}").to_lowercase()
+}
+
+/// Logs a summary of the engine's metrics at the end of a run that took the given time, with one
+/// event for the run as a whole and one for each type of transaction.
+pub fn log_summary(engine: &Engine, elapsed: Duration) {
+    let metrics = &engine.metrics;
+    let processed = metrics.applied() + metrics.rejected();
+    let seconds = elapsed.as_secs_f64();
+    let throughput = if seconds > 0.0 {
+        processed as f64 / seconds
+    } else {
+        0.0
+    };
+    info!(
+        applied = metrics.applied(),
+        rejected = metrics.rejected(),
+        unreadable = metrics.unreadable,
+        flagged = engine.flags.len(),
+        open_disputes = engine.open_disputes(),
+        locked_accounts = engine.locked_accounts(),
+        elapsed_seconds = seconds,
+        transactions_per_second = throughput.round(),
+        "Processed transactions"
+    );
+    for (tx_type, type_metrics) in &metrics.by_type {
+        info!(
+            r#type = %type_label(*tx_type),
+            applied = type_metrics.applied,
+            rejected = type_metrics.rejected,
+            mean_latency_us = (type_metrics.latency.mean() * 1_000_000.0).round(),
+            "Processed transactions by type"
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use crate::engine::Engine;
+    use crate::metrics::{exposition, Histogram};
+    use crate::transaction::{transaction, TxType};
+
+    #[test]
+    fn histogram() {
+        let mut histogram = Histogram::default();
+        histogram.observe(Duration::from_nanos(500));
+        histogram.observe(Duration::from_micros(3));
+        histogram.observe(Duration::from_secs(1));
+
+        let cumulative: Vec<_> = histogram.cumulative().collect();
+        assert_eq!(cumulative[0], (Some(0.000_001), 1));
+        assert_eq!(cumulative[1], (Some(0.000_005), 2));
+        assert_eq!(cumulative[9], (Some(0.05), 2));
+        assert_eq!(cumulative[10], (None, 3));
+        assert_eq!(histogram.count, 3);
+    }
+
+    #[test]
+    fn metrics() {
+        let mut engine = Engine::new();
+        engine
+            .apply(&transaction(TxType::Deposit, 1, 1, Some(10_000)))
+            .unwrap();
+        engine
+            .apply(&transaction(TxType::Withdrawal, 1, 2, Some(20_000)))
+            .unwrap_err();
+        engine
+            .apply(&transaction(TxType::Dispute, 1, 1, None))
+            .unwrap();
+
+        let metrics = exposition(&engine);
+        for line in [
+            "payments_transactions_total{type=\"deposit\",outcome=\"applied\"} 1",
+            "payments_transactions_total{type=\"withdrawal\",outcome=\"rejected\"} 1",
+            "payments_processing_seconds_bucket{type=\"dispute\",le=\"+Inf\"} 1",
+            "payments_processing_seconds_count{type=\"deposit\"} 1",
+            "payments_open_disputes 1",
+            "payments_locked_accounts 0",
+        ] {
+            assert!(metrics.lines().any(|l| l == line), "missing {line}");
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use std::io::Write;
+use std::process;
+use std::str::FromStr;
+
+use serde::Serialize;
+use tracing::error;
+
+use crate::account::Account;
+
+/// Formats that results can be written in.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum OutputFormat {
+    #[default]
+    Csv,
+    // A single pretty-printed JSON array.
+    Json,
+    // One JSON object per line.
+    Jsonl,
+    // Only supported for account details.
+    #[cfg(feature = "parquet")]
+    Parquet,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<OutputFormat, String> {
+        match format {
+            "csv" => Ok(OutputFormat::Csv),
+            "json" => Ok(OutputFormat::Json),
+            "jsonl" => Ok(OutputFormat::Jsonl),
+            #[cfg(feature = "parquet")]
+            "parquet" => Ok(OutputFormat::Parquet),
+            #[cfg(not(feature = "parquet"))]
+            "parquet" => Err("parquet output requires the parquet feature".to_string()),
+            _ => Err(format!(
+                "expected csv, json, jsonl or parquet, found {format}"
+            )),
+        }
+    }
+}
+
+impl OutputFormat {
+    /// Returns whether only account details can be written in this format.
+    pub fn accounts_only(self) -> bool {
+        match self {
+            OutputFormat::Csv | OutputFormat::Json | OutputFormat::Jsonl => false,
+            #[cfg(feature = "parquet")]
+            OutputFormat::Parquet => true,
+        }
+    }
+}
+
+/// Writes records in the given format, exiting if this fails. Parquet output is only supported
+/// for account details, by `write_accounts`.
+pub fn write_output<T: Serialize, W: Write>(
+    records: &[T],
+    format: OutputFormat,
+    name: &str,
+    out: W,
+) {
+    let result = match format {
+        OutputFormat::Csv => {
+            let mut wtr = csv::Writer::from_writer(out);
+            records
+                .iter()
+                .try_for_each(|record| wtr.serialize(record))
+                .and_then(|()| wtr.flush().map_err(csv::Error::from))
+                .map_err(|e| e.to_string())
+        }
+        OutputFormat::Json => {
+            let mut out = out;
+            serde_json::to_writer_pretty(&mut out, records)
+                .and_then(|()| writeln!(out).map_err(serde_json::Error::io))
+                .map_err(|e| e.to_string())
+        }
+        OutputFormat::Jsonl => {
+            let mut out = out;
+            records
+                .iter()
+                .try_for_each(|record| {
+                    serde_json::to_writer(&mut out, record)?;
+                    writeln!(out).map_err(serde_json::Error::io)
+                })
+                .map_err(|e| e.to_string())
+        }
+        #[cfg(feature = "parquet")]
+        OutputFormat::Parquet => unreachable!("parquet output is only written for accounts"),
+    };
+    result
+        .or_else::<String, _>(|e| {
+            error!("Failed to write {name}: {e}");
+            process::exit(1)
+        })
+        .unwrap();
+}
+
+/// Writes account details in the given format, exiting if this fails.
+pub fn write_accounts<W: Write + Send>(accounts: &[&Account], format: OutputFormat, out: W) {
+    #[cfg(feature = "parquet")]
+    if format == OutputFormat::Parquet {
+        crate::columnar::write_accounts(accounts, out)
+            .or_else::<parquet::errors::ParquetError, _>(|e| {
+                error!("Failed to write account details: {e}");
+                process::exit(1)
+            })
+            .unwrap();
+        return;
+    }
+    write_output(accounts, format, "account details", out);
+}
@@ -0,0 +1,142 @@
+use std::fs;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::limits::WithdrawalLimits;
+use crate::rules::RuleSettings;
+use crate::signals::SignalSettings;
+use crate::transaction::TxType;
+
+/// Business rules that vary between lines of business, loaded from a TOML policy file. Rules the
+/// file leaves out keep their defaults, which are the engine's standard behaviour.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Policy {
+    // Types of transaction that may be disputed: deposits, withdrawals or both.
+    pub disputable: Vec<TxType>,
+    // What to do when a disputed deposit is more than the available balance, as holding all of it
+    // would leave the balance negative.
+    pub negative_balance: NegativeBalance,
+    // Whether a chargeback locks the client's account.
+    pub lock_on_chargeback: bool,
+    // What to do with deposits and withdrawals reusing the id of an earlier one.
+    pub duplicate_ids: DuplicateIds,
+    // Limits on how much and how often clients may withdraw.
+    pub limits: WithdrawalLimits,
+    // Settings for the built-in rules that transactions are checked against.
+    pub rules: RuleSettings,
+    // Settings for the fraud signals that transactions are flagged with.
+    pub signals: SignalSettings,
+}
+
+/// How to dispute a deposit when the available balance can't cover it.
+#[derive(Deserialize, Default, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum NegativeBalance {
+    // Hold whatever is available, and leave the platform to cover any chargeback shortfall.
+    #[default]
+    HoldAvailable,
+    // Reject the dispute.
+    Reject,
+}
+
+/// How to treat a deposit or withdrawal whose id has already been used.
+#[derive(Deserialize, Default, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum DuplicateIds {
+    // Apply it as usual. Disputes refer to the first use of the id.
+    #[default]
+    Apply,
+    // Reject it.
+    Reject,
+}
+
+impl Default for Policy {
+    fn default() -> Policy {
+        Policy {
+            disputable: vec![TxType::Deposit],
+            negative_balance: NegativeBalance::default(),
+            lock_on_chargeback: true,
+            duplicate_ids: DuplicateIds::default(),
+            limits: WithdrawalLimits::default(),
+            rules: RuleSettings::default(),
+            signals: SignalSettings::default(),
+        }
+    }
+}
+
+impl Policy {
+    /// Reads a policy from the TOML file at the provided path.
+    pub fn load(path: &Path) -> Result<Policy, String> {
+        let policy: Policy = fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|contents| toml::from_str(&contents).map_err(|e| e.to_string()))
+            .map_err(|e| format!("Failed to read policy {}: {e}", path.display()))?;
+
+        if let Some(tx_type) = policy
+            .disputable
+            .iter()
+            .find(|tx_type| !matches!(tx_type, TxType::Deposit | TxType::Withdrawal))
+        {
+            return Err(format!(
+                "Invalid policy {}: only deposits and withdrawals can be disputable, found {}",
+                path.display(),
+                format!("{tx_type:?}").to_lowercase()
+            ));
+        }
+        if let Some(tier) = policy.limits.undefined_tier() {
+            return Err(format!(
+                "Invalid policy {}: limits tier {tier} is not defined",
+                path.display()
+            ));
+        }
+        Ok(policy)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+    use std::path::Path;
+
+    use crate::limits::Limits;
+    use crate::policy::{DuplicateIds, NegativeBalance, Policy};
+    use crate::rules::RuleSettings;
+    use crate::transaction::{Amount, TxType};
+
+    #[test]
+    fn load_policy() {
+        let policy = Policy::load(Path::new("test/data/policy.toml")).unwrap();
+
+        assert_eq!(policy.disputable, vec![TxType::Deposit, TxType::Withdrawal]);
+        assert_eq!(policy.negative_balance, NegativeBalance::Reject);
+        assert!(!policy.lock_on_chargeback);
+        assert_eq!(policy.duplicate_ids, DuplicateIds::Reject);
+        assert_eq!(
+            policy.rules,
+            RuleSettings {
+                max_withdrawal: Some(Amount(10_000_000)),
+                blocked_clients: HashSet::from([13]),
+                flag_deposits_over: None,
+            }
+        );
+        assert_eq!(policy.limits.window, 3600);
+        assert_eq!(
+            policy.limits.for_client(1),
+            Limits {
+                amount: Some(Amount(50_000_000)),
+                volume: Some(Amount(100_000_000)),
+                count: Some(2),
+            }
+        );
+        assert_eq!(
+            policy.limits.for_client(2),
+            Limits {
+                amount: Some(Amount(5_000_000)),
+                volume: None,
+                count: None,
+            }
+        );
+    }
+}
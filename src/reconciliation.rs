@@ -0,0 +1,129 @@
+use serde::{Serialize, Serializer};
+
+use crate::engine::Engine;
+use crate::ledger::{JournalEntry, LedgerAccount};
+use crate::summary::format_amount;
+
+/// Control totals for a run, reconciling the funds that entered and left client accounts
+/// according to the ledger with the balances of the accounts at the end of the run. Every run
+/// starts without accounts, so the opening balance is always zero.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Default)]
+pub struct Reconciliation {
+    #[serde(serialize_with = "signed_amount")]
+    pub opening: i128,
+    #[serde(serialize_with = "signed_amount")]
+    pub deposits: i128,
+    // Funds withdrawn, less withdrawals returned to clients by disputes and chargebacks.
+    #[serde(serialize_with = "signed_amount")]
+    pub withdrawals: i128,
+    // Funds taken back from clients' held funds by chargebacks of deposits.
+    #[serde(serialize_with = "signed_amount")]
+    pub chargebacks: i128,
+    // Parts of chargebacks of deposits that the platform covered, as the client held too little.
+    #[serde(serialize_with = "signed_amount")]
+    pub chargeback_losses: i128,
+    #[serde(serialize_with = "signed_amount")]
+    pub available: i128,
+    #[serde(serialize_with = "signed_amount")]
+    pub pending: i128,
+    #[serde(serialize_with = "signed_amount")]
+    pub held: i128,
+    // The sum of the total balances of every account.
+    #[serde(serialize_with = "signed_amount")]
+    pub closing: i128,
+    // Whether opening + deposits - withdrawals - chargebacks == closing.
+    pub balanced: bool,
+}
+
+impl Reconciliation {
+    /// Adds a journal entry to the totals of funds moved between client accounts and the
+    /// platform's. Disputes, resolves and chargebacks are posted under the id of the transaction
+    /// they refer to, so `disputed_withdrawal` tells whether that transaction was a withdrawal.
+    pub fn post(&mut self, entry: &JournalEntry, disputed_withdrawal: bool) {
+        let amount = i128::from(entry.amount.0);
+        match (entry.from, entry.to) {
+            (LedgerAccount::Clearing, LedgerAccount::Available(_) | LedgerAccount::Pending(_)) => {
+                self.deposits += amount;
+            }
+            (LedgerAccount::Available(_), LedgerAccount::Clearing) => {
+                self.withdrawals += amount;
+            }
+            // A disputed withdrawal is returned to held funds, and taken again if resolved. Any
+            // part of a chargeback of a withdrawal that the client's held funds couldn't cover is
+            // returned by the platform.
+            (LedgerAccount::Clearing | LedgerAccount::ChargebackLoss, to)
+                if to.client().is_some() =>
+            {
+                self.withdrawals -= amount;
+            }
+            (LedgerAccount::Held(_), LedgerAccount::Clearing) if disputed_withdrawal => {
+                self.withdrawals += amount;
+            }
+            (LedgerAccount::Held(_), LedgerAccount::Clearing) => self.chargebacks += amount,
+            (LedgerAccount::ChargebackLoss, LedgerAccount::Clearing) => {
+                self.chargeback_losses += amount;
+            }
+            _ => {}
+        }
+    }
+
+    /// Completes the totals of funds the engine has moved with the balances of its accounts.
+    pub fn new(engine: &Engine) -> Reconciliation {
+        let mut totals = engine.control_totals();
+        for account in engine.accounts.values() {
+            totals.available += i128::from(account.available.0);
+            totals.pending += i128::from(account.pending.0);
+            totals.held += i128::from(account.held.0);
+            totals.closing += i128::from(account.total.0);
+        }
+        totals.balanced =
+            totals.opening + totals.deposits - totals.withdrawals - totals.chargebacks
+                == totals.closing;
+        totals
+    }
+}
+
+fn signed_amount<S: Serializer>(amount: &i128, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(&format_amount(*amount))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::engine::Engine;
+    use crate::policy::Policy;
+    use crate::reconciliation::Reconciliation;
+    use crate::transaction::{transaction, TxType};
+
+    #[test]
+    fn control_totals() {
+        let mut engine = Engine::new().with_policy(Policy {
+            disputable: vec![TxType::Deposit, TxType::Withdrawal],
+            ..Policy::default()
+        });
+        for transaction in [
+            transaction(TxType::Deposit, 1, 1, Some(100_000)),
+            transaction(TxType::Withdrawal, 1, 2, Some(30_000)),
+            transaction(TxType::Deposit, 2, 3, Some(50_000)),
+            transaction(TxType::Withdrawal, 2, 4, Some(40_000)),
+            // Charging back more than client 2 has left costs the platform.
+            transaction(TxType::Dispute, 2, 3, None),
+            transaction(TxType::Chargeback, 2, 3, None),
+            // Disputing a withdrawal returns it to held funds, until it is resolved.
+            transaction(TxType::Dispute, 1, 2, None),
+            transaction(TxType::Resolve, 1, 2, None),
+            transaction(TxType::Dispute, 1, 2, None),
+        ] {
+            engine.apply(&transaction).unwrap();
+        }
+
+        let totals = Reconciliation::new(&engine);
+        assert_eq!(totals.deposits, 150_000);
+        assert_eq!(totals.withdrawals, 40_000);
+        assert_eq!(totals.chargebacks, 10_000);
+        assert_eq!(totals.chargeback_losses, 40_000);
+        assert_eq!(totals.available, 70_000);
+        assert_eq!(totals.held, 30_000);
+        assert_eq!(totals.closing, 100_000);
+        assert!(totals.balanced);
+    }
+}
@@ -0,0 +1,55 @@
+use std::fmt;
+
+use crate::limits::Limit;
+
+/// Reasons a transaction may be rejected by the engine. A rejected transaction leaves account
+/// balances unchanged, except that its timestamp still moves the engine's clock forward, maturing
+/// deposits and expiring disputes that were due by then.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Rejection {
+    InsufficientFunds,
+    // The withdrawal would breach one of the client's limits.
+    WithdrawalLimit(Limit),
+    AccountLocked,
+    AccountClosed,
+    UnknownAccount,
+    UnknownTransaction,
+    NotOwner,
+    NotDisputable,
+    NotDisputed,
+    DuplicateTransaction,
+    // Rejected by the named rule.
+    RuleViolation(&'static str),
+    DisputeWindowClosed,
+    MissingAdminDetails,
+    NonZeroBalance,
+    OutOfOrder,
+    // The client's total balance would be more than the largest amount that can be represented.
+    BalanceOverflow,
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let reason = match self {
+            Rejection::InsufficientFunds => "insufficient funds",
+            Rejection::AccountLocked => "account is locked",
+            Rejection::AccountClosed => "account is closed",
+            Rejection::UnknownAccount => "account does not exist",
+            Rejection::UnknownTransaction => "transaction does not exist",
+            Rejection::NotOwner => "dispute must be initiated by owner of disputed transaction",
+            Rejection::NotDisputable => "transaction type cannot be disputed",
+            Rejection::NotDisputed => "transaction is not disputed",
+            Rejection::DuplicateTransaction => "transaction id has already been used",
+            Rejection::DisputeWindowClosed => "transaction is too old to dispute",
+            Rejection::MissingAdminDetails => "admin actions require an operator and a reason",
+            Rejection::NonZeroBalance => "account balance is not zero",
+            Rejection::OutOfOrder => "timestamp is earlier than a previous transaction",
+            Rejection::BalanceOverflow => "account balance would be too large",
+            Rejection::WithdrawalLimit(limit) => {
+                return write!(f, "withdrawal {limit} limit exceeded");
+            }
+            Rejection::RuleViolation(rule) => return write!(f, "rejected by rule {rule}"),
+        };
+        write!(f, "{reason}")
+    }
+}
@@ -0,0 +1,119 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+
+use crate::transaction::Transaction;
+
+/// Buffers transactions so that those arriving slightly out of timestamp order can be released in
+/// order. A transaction is held until a transaction at least `window` seconds newer has been
+/// seen. Transactions without a timestamp are treated as occurring at the latest timestamp seen.
+/// Each transaction is buffered along with where it was read from, of type `L`.
+pub struct ReorderBuffer<L> {
+    window: u64,
+    latest: u64,
+    sequence: u64,
+    heap: BinaryHeap<Reverse<Buffered<L>>>,
+}
+
+// A buffered transaction and where it was read from, ordered by timestamp and then by arrival.
+struct Buffered<L> {
+    timestamp: u64,
+    sequence: u64,
+    location: L,
+    transaction: Transaction,
+}
+
+impl<L> ReorderBuffer<L> {
+    pub fn new(window: u64) -> ReorderBuffer<L> {
+        ReorderBuffer {
+            window,
+            latest: 0,
+            sequence: 0,
+            heap: BinaryHeap::new(),
+        }
+    }
+
+    /// Adds a transaction to the buffer, along with where it was read from.
+    pub fn push(&mut self, location: L, transaction: Transaction) {
+        let timestamp = transaction.timestamp.unwrap_or(self.latest);
+        self.latest = self.latest.max(timestamp);
+        self.sequence += 1;
+        self.heap.push(Reverse(Buffered {
+            timestamp,
+            sequence: self.sequence,
+            location,
+            transaction,
+        }));
+    }
+
+    /// Removes the earliest transaction from the buffer, if it falls outside the window.
+    pub fn pop_ready(&mut self) -> Option<(L, Transaction)> {
+        let Reverse(earliest) = self.heap.peek()?;
+        if earliest.timestamp.saturating_add(self.window) > self.latest {
+            return None;
+        }
+        self.pop()
+    }
+
+    /// Removes the earliest transaction from the buffer, regardless of the window.
+    pub fn pop(&mut self) -> Option<(L, Transaction)> {
+        self.heap
+            .pop()
+            .map(|Reverse(buffered)| (buffered.location, buffered.transaction))
+    }
+}
+
+impl<L> PartialEq for Buffered<L> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<L> Eq for Buffered<L> {}
+
+impl<L> PartialOrd for Buffered<L> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<L> Ord for Buffered<L> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.timestamp, self.sequence).cmp(&(other.timestamp, other.sequence))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::reorder::ReorderBuffer;
+    use crate::transaction::{transaction, Transaction, TxType};
+
+    fn deposit(id: u32, timestamp: u64) -> Transaction {
+        Transaction {
+            timestamp: Some(timestamp),
+            ..transaction(TxType::Deposit, 1, id, None)
+        }
+    }
+
+    #[test]
+    fn releases_in_timestamp_order() {
+        let mut buffer = ReorderBuffer::new(10);
+        let mut released = Vec::new();
+        for transaction in [
+            deposit(1, 100),
+            deposit(2, 95),
+            deposit(3, 108),
+            deposit(4, 120),
+        ] {
+            buffer.push(u64::from(transaction.id), transaction);
+            while let Some((row, _)) = buffer.pop_ready() {
+                released.push(row);
+            }
+        }
+        assert_eq!(released, vec![2, 1, 3]);
+
+        while let Some((row, _)) = buffer.pop() {
+            released.push(row);
+        }
+        assert_eq!(released, vec![2, 1, 3, 4]);
+    }
+}
@@ -0,0 +1,50 @@
+use std::str::FromStr;
+
+/// A point in the input at which to stop processing, so that balances can be reported as they
+/// were at that point.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplayPoint {
+    // Stop after the given (1-based) row of input, not counting the header.
+    Row(u64),
+    // Stop after the first row with the given transaction id.
+    Transaction(u32),
+    // Stop before the first transaction timestamped after the given time. Transactions without a
+    // timestamp before it are applied, and those after it aren't.
+    Time(u64),
+}
+
+impl ReplayPoint {
+    /// Returns whether a transaction with the given timestamp falls after the replay point, so that
+    /// neither it nor anything after it should be applied. A transaction without a timestamp can't
+    /// be placed in time, so it never reaches the replay point itself.
+    pub fn excludes(self, timestamp: Option<u64>) -> bool {
+        match self {
+            ReplayPoint::Time(time) => timestamp.is_some_and(|timestamp| timestamp > time),
+            ReplayPoint::Row(_) | ReplayPoint::Transaction(_) => false,
+        }
+    }
+
+    /// Returns whether the replay point is reached once the transaction with the given id has
+    /// been applied.
+    pub fn ends_at(self, id: u32) -> bool {
+        self == ReplayPoint::Transaction(id)
+    }
+}
+
+impl FromStr for ReplayPoint {
+    type Err = String;
+
+    /// Parses a replay point of the form `row:N`, `tx:ID` or `time:T`.
+    fn from_str(point: &str) -> Result<ReplayPoint, String> {
+        let (kind, value) = point
+            .split_once(':')
+            .ok_or_else(|| format!("expected row:N, tx:ID or time:T, found {point}"))?;
+        let invalid = |e| format!("invalid {kind} in {point}: {e}");
+        match kind {
+            "row" => value.parse().map(ReplayPoint::Row).map_err(invalid),
+            "tx" => value.parse().map(ReplayPoint::Transaction).map_err(invalid),
+            "time" => value.parse().map(ReplayPoint::Time).map_err(invalid),
+            _ => Err(format!("expected row:N, tx:ID or time:T, found {point}")),
+        }
+    }
+}
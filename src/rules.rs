@@ -0,0 +1,213 @@
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+
+use crate::account::Account;
+use crate::history::ClientHistory;
+use crate::transaction::{Amount, Transaction, TxType};
+
+/// What a rule decides about a transaction.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Verdict {
+    // Let the transaction through to the engine.
+    Allow,
+    // Reject the transaction, giving the rule's name as the reason.
+    Reject,
+    // Apply the transaction, but record a flag with the rule's name.
+    Flag,
+}
+
+/// A check run on each transaction before it is applied. Rules see the client's account as it
+/// stands, if the client has one, and the history of the client's applied transactions if they
+/// use it, but can't change either.
+pub trait Rule {
+    /// A short name identifying the rule in rejections and flags.
+    fn name(&self) -> &'static str;
+
+    fn check(
+        &self,
+        transaction: &Transaction,
+        account: Option<&Account>,
+        history: ClientHistory<'_>,
+    ) -> Verdict;
+
+    /// Whether the rule looks at the client's history. The engine only keeps a history of applied
+    /// transactions if a rule or a statement needs it, so other rules are given an empty one.
+    fn uses_history(&self) -> bool {
+        false
+    }
+}
+
+/// A transaction that was applied despite a rule flagging it.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Flag {
+    pub client: u16,
+    pub tx: u32,
+    #[serde(rename = "type")]
+    pub tx_type: TxType,
+    // The name of the rule that raised the flag.
+    pub code: &'static str,
+    pub timestamp: Option<u64>,
+}
+
+/// Settings for the built-in rules, read from the `[rules]` table of a policy file. Rules without
+/// settings aren't registered.
+#[derive(Deserialize, Default, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct RuleSettings {
+    pub max_withdrawal: Option<Amount>,
+    pub blocked_clients: HashSet<u16>,
+    pub flag_deposits_over: Option<Amount>,
+}
+
+impl RuleSettings {
+    /// Creates the built-in rules that have settings, in the order they should be checked.
+    pub fn rules(&self) -> Vec<Box<dyn Rule>> {
+        let mut rules: Vec<Box<dyn Rule>> = Vec::new();
+        if !self.blocked_clients.is_empty() {
+            rules.push(Box::new(BlockedClients(self.blocked_clients.clone())));
+        }
+        if let Some(limit) = self.max_withdrawal {
+            rules.push(Box::new(MaxWithdrawal(limit)));
+        }
+        if let Some(threshold) = self.flag_deposits_over {
+            rules.push(Box::new(LargeDeposit(threshold)));
+        }
+        rules
+    }
+}
+
+/// Rejects every transaction of the given clients.
+pub struct BlockedClients(pub HashSet<u16>);
+
+impl Rule for BlockedClients {
+    fn name(&self) -> &'static str {
+        "blocked-client"
+    }
+
+    fn check(
+        &self,
+        transaction: &Transaction,
+        _: Option<&Account>,
+        _: ClientHistory<'_>,
+    ) -> Verdict {
+        if self.0.contains(&transaction.client) {
+            Verdict::Reject
+        } else {
+            Verdict::Allow
+        }
+    }
+}
+
+/// Rejects single withdrawals of more than the limit.
+pub struct MaxWithdrawal(pub Amount);
+
+impl Rule for MaxWithdrawal {
+    fn name(&self) -> &'static str {
+        "max-withdrawal"
+    }
+
+    fn check(
+        &self,
+        transaction: &Transaction,
+        _: Option<&Account>,
+        _: ClientHistory<'_>,
+    ) -> Verdict {
+        match (transaction.tx_type, transaction.amount) {
+            (TxType::Withdrawal, Some(amount)) if amount > self.0 => Verdict::Reject,
+            _ => Verdict::Allow,
+        }
+    }
+}
+
+/// Flags single deposits of more than the threshold.
+pub struct LargeDeposit(pub Amount);
+
+impl Rule for LargeDeposit {
+    fn name(&self) -> &'static str {
+        "large-deposit"
+    }
+
+    fn check(
+        &self,
+        transaction: &Transaction,
+        _: Option<&Account>,
+        _: ClientHistory<'_>,
+    ) -> Verdict {
+        match (transaction.tx_type, transaction.amount) {
+            (TxType::Deposit, Some(amount)) if amount > self.0 => Verdict::Flag,
+            _ => Verdict::Allow,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+
+    use crate::account::Account;
+    use crate::engine::Engine;
+    use crate::history::ClientHistory;
+    use crate::rejection::Rejection;
+    use crate::rules::{BlockedClients, LargeDeposit, MaxWithdrawal, Rule, Verdict};
+    use crate::transaction::{transaction, Amount, Transaction, TxType};
+
+    // Flags every transaction from a client with no account yet.
+    struct NewClient;
+
+    impl Rule for NewClient {
+        fn name(&self) -> &'static str {
+            "new-client"
+        }
+
+        fn check(
+            &self,
+            _: &Transaction,
+            account: Option<&Account>,
+            _: ClientHistory<'_>,
+        ) -> Verdict {
+            if account.is_none() {
+                Verdict::Flag
+            } else {
+                Verdict::Allow
+            }
+        }
+    }
+
+    #[test]
+    fn rules() {
+        let mut engine = Engine::new()
+            .with_rule(Box::new(BlockedClients(HashSet::from([2]))))
+            .with_rule(Box::new(MaxWithdrawal(Amount(50_000))))
+            .with_rule(Box::new(LargeDeposit(Amount(100_000))))
+            .with_rule(Box::new(NewClient));
+
+        let deposit = transaction(TxType::Deposit, 1, 1, Some(200_000));
+        engine.apply(&deposit).unwrap();
+        assert_eq!(
+            engine
+                .flags
+                .iter()
+                .map(|flag| (flag.tx, flag.code))
+                .collect::<Vec<_>>(),
+            vec![(1, "large-deposit"), (1, "new-client")]
+        );
+
+        assert_eq!(
+            engine.apply(&transaction(TxType::Withdrawal, 1, 2, Some(60_000))),
+            Err(Rejection::RuleViolation("max-withdrawal"))
+        );
+        engine
+            .apply(&transaction(TxType::Withdrawal, 1, 3, Some(50_000)))
+            .unwrap();
+        assert_eq!(
+            engine.apply(&transaction(TxType::Deposit, 2, 4, Some(10_000))),
+            Err(Rejection::RuleViolation("blocked-client"))
+        );
+
+        // Rejected transactions leave no flags behind.
+        assert_eq!(engine.flags.len(), 2);
+        assert_eq!(engine.accounts[&1].available, Amount(150_000));
+        assert!(!engine.accounts.contains_key(&2));
+    }
+}
@@ -0,0 +1,186 @@
+use std::io::Cursor;
+use std::process;
+
+use serde::Serialize;
+use tiny_http::{Header, Method, Request, Response, Server};
+use tracing::{error, info, warn};
+
+use crate::config::Config;
+use crate::engine::Engine;
+use crate::input::TransactionReader;
+use crate::metrics;
+use crate::output::{write_accounts, OutputFormat};
+use crate::strict;
+
+/// The outcome of a batch of posted transactions.
+#[derive(Serialize, Debug, Default, PartialEq)]
+struct BatchResult {
+    applied: u64,
+    rejected: Vec<RejectedRow>,
+}
+
+/// A posted row that wasn't applied, and why.
+#[derive(Serialize, Debug, PartialEq)]
+struct RejectedRow {
+    line: u64,
+    reason: String,
+}
+
+/// Listens for HTTP requests on the given address until the process is stopped, exiting if the
+/// address cannot be bound. Transactions posted to `/transactions`, in the configured input format,
+/// are applied to a single engine as they arrive, and its accounts are served from `/accounts` and
+/// `/accounts/{client}` in the configured output format. Metrics are served from `/metrics`.
+pub fn serve(listen: &str, config: &Config) {
+    let server = Server::http(listen)
+        .or_else::<Box<dyn std::error::Error + Send + Sync>, _>(|e| {
+            error!("Failed to listen on {listen}: {e}");
+            process::exit(1)
+        })
+        .unwrap();
+    info!("Listening on {listen}");
+
+    let mut engine = config.new_engine();
+    for mut request in server.incoming_requests() {
+        let response = handle(&mut engine, config, &mut request);
+        if let Err(e) = request.respond(response) {
+            warn!("Failed to send response: {e}");
+        }
+    }
+}
+
+/// Routes a request, applying any transactions it carries to the engine.
+fn handle(
+    engine: &mut Engine,
+    config: &Config,
+    request: &mut Request,
+) -> Response<Cursor<Vec<u8>>> {
+    let url = request.url().to_string();
+    match (request.method(), url.trim_end_matches('/')) {
+        (Method::Post, "/transactions") => {
+            let mut body = String::new();
+            if let Err(e) = request.as_reader().read_to_string(&mut body) {
+                return error(400, &format!("Failed to read request body: {e}"));
+            }
+            match apply_batch(engine, config, body) {
+                Ok(result) => {
+                    let body = serde_json::to_vec(&result).expect("batch results serialize");
+                    Response::from_data(body).with_header(content_type("application/json"))
+                }
+                Err(e) => error(400, &e),
+            }
+        }
+        (Method::Get, "/metrics") => Response::from_string(metrics::exposition(engine))
+            .with_header(content_type("text/plain; version=0.0.4")),
+        (Method::Get, "/accounts") => accounts(engine, None, config.output_format),
+        (Method::Get, path) => match path.strip_prefix("/accounts/").map(str::parse) {
+            Some(Ok(client)) => accounts(engine, Some(client), config.output_format),
+            Some(Err(_)) => error(400, "Client must be a number"),
+            None => error(404, "Not found"),
+        },
+        _ => error(404, "Not found"),
+    }
+}
+
+/// Applies the transactions in a request body, returning how many were applied and why the rest
+/// weren't. Fails without applying anything if the body cannot be read as transactions at all.
+fn apply_batch(engine: &mut Engine, config: &Config, body: String) -> Result<BatchResult, String> {
+    let mut transactions = TransactionReader::from_reader(
+        Box::new(Cursor::new(body)),
+        config.input_format,
+        config.strict,
+    )?;
+
+    let mut result = BatchResult::default();
+    while let Some((line, next_row)) = transactions.next_row() {
+        if next_row.is_err() {
+            engine.metrics.unreadable += 1;
+        }
+        let applied = next_row
+            .and_then(|transaction| {
+                if config.strict {
+                    strict::check_fields(&transaction).map_err(|(_, e)| e)?;
+                }
+                engine.apply(&transaction).map_err(|e| e.to_string())
+            })
+            .map_err(|reason| RejectedRow { line, reason });
+        match applied {
+            Ok(()) => result.applied += 1,
+            Err(rejected) => result.rejected.push(rejected),
+        }
+    }
+    Ok(result)
+}
+
+/// Responds with the engine's accounts, or only the given client's account.
+fn accounts(
+    engine: &Engine,
+    client: Option<u16>,
+    format: OutputFormat,
+) -> Response<Cursor<Vec<u8>>> {
+    let accounts = engine.client_accounts(client);
+    if client.is_some() && accounts.is_empty() {
+        return error(404, "No such client");
+    }
+
+    let mut body = Vec::new();
+    write_accounts(&accounts, format, &mut body);
+    let content_type = match format {
+        OutputFormat::Csv => content_type("text/csv"),
+        OutputFormat::Json => content_type("application/json"),
+        OutputFormat::Jsonl => content_type("application/x-ndjson"),
+        #[cfg(feature = "parquet")]
+        OutputFormat::Parquet => content_type("application/vnd.apache.parquet"),
+    };
+    Response::from_data(body).with_header(content_type)
+}
+
+fn error(status: u16, message: &str) -> Response<Cursor<Vec<u8>>> {
+    Response::from_string(format!("{message}\n"))
+        .with_status_code(status)
+        .with_header(content_type("text/plain"))
+}
+
+fn content_type(value: &str) -> Header {
+    Header::from_bytes("Content-Type", value).expect("content types are valid header values")
+}
+
+#[cfg(test)]
+mod test {
+    use crate::config::Config;
+    use crate::serve::{apply_batch, BatchResult, RejectedRow};
+
+    #[test]
+    fn batches() {
+        let config = Config::default();
+        let mut engine = config.new_engine();
+
+        let result = apply_batch(
+            &mut engine,
+            &config,
+            "type,client,tx,amount\ndeposit,1,1,2.0\nwithdrawal,1,2,3.0\nbogus,1,3,1.0\n"
+                .to_string(),
+        )
+        .unwrap();
+
+        assert_eq!(result.applied, 1);
+        assert_eq!(result.rejected.len(), 2);
+        assert_eq!(result.rejected[0].line, 3);
+        assert_eq!(result.rejected[1].line, 4);
+
+        // Later batches apply to the same accounts.
+        let result = apply_batch(
+            &mut engine,
+            &config,
+            "type,client,tx,amount\nwithdrawal,1,4,1.5\n".to_string(),
+        )
+        .unwrap();
+        assert_eq!(
+            result,
+            BatchResult {
+                applied: 1,
+                rejected: Vec::<RejectedRow>::new(),
+            }
+        );
+        assert_eq!(engine.accounts[&1].available.to_string(), "0.5");
+    }
+}
@@ -0,0 +1,161 @@
+use serde::Deserialize;
+
+use crate::account::Account;
+use crate::history::ClientHistory;
+use crate::rules::{Rule, Verdict};
+use crate::transaction::{Transaction, TxType};
+
+/// Settings for the fraud signals, read from the `[signals]` table of a policy file. Signals flag
+/// transactions without rejecting them, and are only raised when they have settings.
+#[derive(Deserialize, Default, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct SignalSettings {
+    // Flag withdrawals of the whole available balance within this many seconds of a deposit.
+    pub rapid_withdrawal: Option<u64>,
+    // Flag a client's disputes once they have raised this many.
+    pub repeated_disputes: Option<u64>,
+    // Flag withdrawals within this many seconds of a dispute.
+    pub withdrawal_after_dispute: Option<u64>,
+}
+
+impl SignalSettings {
+    /// Creates the rules raising the signals that have settings.
+    pub fn rules(&self) -> Vec<Box<dyn Rule>> {
+        let mut rules: Vec<Box<dyn Rule>> = Vec::new();
+        if let Some(within) = self.rapid_withdrawal {
+            rules.push(Box::new(RapidWithdrawal(within)));
+        }
+        if let Some(count) = self.repeated_disputes {
+            rules.push(Box::new(RepeatedDisputes(count)));
+        }
+        if let Some(within) = self.withdrawal_after_dispute {
+            rules.push(Box::new(WithdrawalAfterDispute(within)));
+        }
+        rules
+    }
+}
+
+/// Returns whether the transaction came within the given number of seconds after an earlier
+/// one. Without both timestamps there's no telling, so it didn't.
+fn within(earlier: &Transaction, later: &Transaction, seconds: u64) -> bool {
+    match (earlier.timestamp, later.timestamp) {
+        (Some(earlier), Some(later)) => later.saturating_sub(earlier) <= seconds,
+        _ => false,
+    }
+}
+
+/// Flags a withdrawal of the whole available balance that immediately follows a deposit, as when
+/// funds are passed straight through an account. Without both timestamps, the withdrawal need only
+/// be the client's next transaction.
+pub struct RapidWithdrawal(pub u64);
+
+impl Rule for RapidWithdrawal {
+    fn name(&self) -> &'static str {
+        "rapid-withdrawal"
+    }
+
+    fn uses_history(&self) -> bool {
+        true
+    }
+
+    fn check(
+        &self,
+        transaction: &Transaction,
+        account: Option<&Account>,
+        history: ClientHistory<'_>,
+    ) -> Verdict {
+        if transaction.tx_type != TxType::Withdrawal
+            || account.is_none_or(|account| transaction.amount != Some(account.available))
+        {
+            return Verdict::Allow;
+        }
+        match history.iter().next_back() {
+            Some(last)
+                if last.transaction.tx_type == TxType::Deposit
+                    && (last.transaction.timestamp.is_none()
+                        || transaction.timestamp.is_none()
+                        || within(&last.transaction, transaction, self.0)) =>
+            {
+                Verdict::Flag
+            }
+            _ => Verdict::Allow,
+        }
+    }
+}
+
+/// Flags a client's disputes once they have raised the given number of them, counting the
+/// dispute being checked.
+pub struct RepeatedDisputes(pub u64);
+
+impl Rule for RepeatedDisputes {
+    fn name(&self) -> &'static str {
+        "repeated-disputes"
+    }
+
+    fn uses_history(&self) -> bool {
+        true
+    }
+
+    fn check(
+        &self,
+        transaction: &Transaction,
+        _: Option<&Account>,
+        history: ClientHistory<'_>,
+    ) -> Verdict {
+        if transaction.tx_type != TxType::Dispute {
+            return Verdict::Allow;
+        }
+        let disputes = history
+            .iter()
+            .filter(|entry| entry.transaction.tx_type == TxType::Dispute)
+            .count() as u64;
+        if disputes + 1 >= self.0 {
+            Verdict::Flag
+        } else {
+            Verdict::Allow
+        }
+    }
+}
+
+/// Flags withdrawals made within the given number of seconds after the client raised a dispute,
+/// as when a client disputes a deposit they are about to withdraw.
+pub struct WithdrawalAfterDispute(pub u64);
+
+impl Rule for WithdrawalAfterDispute {
+    fn name(&self) -> &'static str {
+        "withdrawal-after-dispute"
+    }
+
+    fn uses_history(&self) -> bool {
+        true
+    }
+
+    fn check(
+        &self,
+        transaction: &Transaction,
+        _: Option<&Account>,
+        history: ClientHistory<'_>,
+    ) -> Verdict {
+        if transaction.tx_type != TxType::Withdrawal || transaction.timestamp.is_none() {
+            return Verdict::Allow;
+        }
+        // Timestamps never go backwards, so the search can stop at the first earlier transaction
+        // that is too old.
+        let recent_dispute = history
+            .iter()
+            .rev()
+            .take_while(|entry| {
+                entry.transaction.timestamp.is_none()
+                    || within(&entry.transaction, transaction, self.0)
+            })
+            .any(|entry| {
+                entry.transaction.tx_type == TxType::Dispute
+                    && within(&entry.transaction, transaction, self.0)
+            });
+        if recent_dispute {
+            Verdict::Flag
+        } else {
+            Verdict::Allow
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use serde::Serialize;
+
+use crate::history::HistoryEntry;
+use crate::transaction::{Amount, TxType};
+
+/// A line of a client's account statement, showing an applied transaction, the running balances
+/// after it and any flags raised on it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StatementLine {
+    pub client: u16,
+    #[serde(rename = "type")]
+    pub tx_type: TxType,
+    #[serde(rename = "tx")]
+    pub id: u32,
+    pub amount: Option<Amount>,
+    pub timestamp: Option<u64>,
+    pub available: Amount,
+    pub pending: Amount,
+    pub held: Amount,
+    pub total: Amount,
+    pub locked: bool,
+    // Codes of the flags raised on the transaction, separated by semicolons.
+    pub flags: String,
+}
+
+/// Returns the statement for the given client, or for every client if none is given. Each
+/// client's lines are in the order their transactions were applied.
+pub fn statement(history: &[HistoryEntry], client: Option<u16>) -> Vec<StatementLine> {
+    let mut lines: Vec<StatementLine> = history
+        .iter()
+        .filter(|entry| client.is_none_or(|client| entry.transaction.client == client))
+        .map(|entry| StatementLine {
+            client: entry.transaction.client,
+            tx_type: entry.transaction.tx_type,
+            id: entry.transaction.id,
+            amount: entry.transaction.amount,
+            timestamp: entry.transaction.timestamp,
+            available: entry.account.available,
+            pending: entry.account.pending,
+            held: entry.account.held,
+            total: entry.account.total,
+            locked: entry.account.locked,
+            flags: entry.flags.join(";"),
+        })
+        .collect();
+
+    // The sort is stable, so each client's lines stay in chronological order.
+    lines.sort_by_key(|line| line.client);
+    lines
+}
@@ -0,0 +1,107 @@
+use crate::transaction::{Amount, Transaction, TxType};
+
+// Columns every csv file must start with, and those that may follow them, in order.
+const REQUIRED_COLUMNS: [&str; 4] = ["type", "client", "tx", "amount"];
+const OPTIONAL_COLUMNS: [&str; 3] = ["operator", "reason", "timestamp"];
+
+/// Checks that a csv header names the required columns, followed by any of the optional columns,
+/// in order and without surrounding whitespace.
+pub fn check_header<'a, I: IntoIterator<Item = &'a [u8]>>(header: I) -> Result<(), String> {
+    let header: Vec<&[u8]> = header.into_iter().collect();
+    let valid = header.len() >= REQUIRED_COLUMNS.len()
+        && header
+            .iter()
+            .zip(REQUIRED_COLUMNS)
+            .all(|(column, expected)| *column == expected.as_bytes())
+        && {
+            // Each remaining column must come after the previous one in the optional columns.
+            let mut optional = OPTIONAL_COLUMNS.iter();
+            header[REQUIRED_COLUMNS.len()..]
+                .iter()
+                .all(|column| optional.any(|expected| *column == expected.as_bytes()))
+        };
+    if valid {
+        return Ok(());
+    }
+
+    let found: Vec<_> = header
+        .iter()
+        .map(|column| String::from_utf8_lossy(column))
+        .collect();
+    Err(format!(
+        "expected {} optionally followed by {}, found {}",
+        REQUIRED_COLUMNS.join(","),
+        OPTIONAL_COLUMNS.join(","),
+        found.join(",")
+    ))
+}
+
+/// Checks that a transaction has an amount if and only if its type needs one, and that the amount
+/// isn't zero. On failure, gives the name of the offending field along with the problem.
+pub fn check_fields(transaction: &Transaction) -> Result<(), (&'static str, String)> {
+    let tx_type = format!("{:?}", transaction.tx_type).to_lowercase();
+    match (transaction.tx_type, transaction.amount) {
+        (TxType::Deposit | TxType::Withdrawal, None) => {
+            Err(("amount", format!("amount is required for a {tx_type}")))
+        }
+        (TxType::Deposit | TxType::Withdrawal, Some(Amount(0))) => {
+            Err(("amount", "amount must not be zero".to_string()))
+        }
+        (
+            TxType::Dispute
+            | TxType::Resolve
+            | TxType::Chargeback
+            | TxType::Lock
+            | TxType::Unlock
+            | TxType::Close,
+            Some(_),
+        ) => Err(("amount", format!("amount is not allowed for a {tx_type}"))),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::strict::{check_fields, check_header};
+    use crate::transaction::{transaction, TxType};
+
+    fn header(columns: &str) -> Vec<&[u8]> {
+        columns.split(',').map(str::as_bytes).collect()
+    }
+
+    #[test]
+    fn headers() {
+        assert!(check_header(header("type,client,tx,amount")).is_ok());
+        assert!(check_header(header("type,client,tx,amount,timestamp")).is_ok());
+        assert!(check_header(header("type,client,tx,amount,operator,reason")).is_ok());
+        assert!(check_header(header("type, client,tx,amount")).is_err());
+        assert!(check_header(header("type,client,tx")).is_err());
+        assert!(check_header(header("type,client,tx,amount,reason,operator")).is_err());
+        assert!(check_header(header("type,client,tx,amount,extra")).is_err());
+    }
+
+    #[test]
+    fn fields() {
+        assert!(check_fields(&transaction(TxType::Deposit, 1, 1, Some(1))).is_ok());
+        assert!(check_fields(&transaction(TxType::Dispute, 1, 1, None)).is_ok());
+        assert_eq!(
+            check_fields(&transaction(TxType::Withdrawal, 1, 1, None)),
+            Err(("amount", "amount is required for a withdrawal".to_string()))
+        );
+        assert_eq!(
+            check_fields(&transaction(TxType::Deposit, 1, 1, Some(0))),
+            Err(("amount", "amount must not be zero".to_string()))
+        );
+        assert_eq!(
+            check_fields(&transaction(TxType::Chargeback, 1, 1, Some(1))),
+            Err((
+                "amount",
+                "amount is not allowed for a chargeback".to_string()
+            ))
+        );
+        assert_eq!(
+            check_fields(&transaction(TxType::Lock, 1, 1, Some(1))),
+            Err(("amount", "amount is not allowed for a lock".to_string()))
+        );
+    }
+}
@@ -0,0 +1,96 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{self, Write};
+
+use crate::explain::Step;
+use crate::reconciliation::Reconciliation;
+use crate::rejection::Rejection;
+use crate::transaction::{Transaction, TxType};
+
+/// Counts of the rows read and what became of them, for checking input before it is processed.
+#[derive(Default, Debug)]
+pub struct Summary {
+    pub rows: u64,
+    // Rows that couldn't be read as transactions.
+    pub unreadable: u64,
+    pub by_type: BTreeMap<TxType, u64>,
+    pub rejections: HashMap<Rejection, u64>,
+    pub clients: HashSet<u16>,
+    // Whether reading stopped at an invalid row, in strict mode.
+    pub invalid: bool,
+    // When explaining a client's account, each of their rows that was applied or rejected.
+    pub steps: Vec<Step>,
+}
+
+impl Summary {
+    /// Counts a transaction that has been read.
+    pub fn read(&mut self, transaction: &Transaction) {
+        *self.by_type.entry(transaction.tx_type).or_default() += 1;
+        self.clients.insert(transaction.client);
+    }
+
+    /// Counts a transaction that the engine rejected.
+    pub fn reject(&mut self, rejection: Rejection) {
+        *self.rejections.entry(rejection).or_default() += 1;
+    }
+
+    /// The fraction of rows that were unreadable or rejected.
+    pub fn rejection_rate(&self) -> f64 {
+        if self.rows == 0 {
+            return 0.0;
+        }
+        let rejected = self.unreadable + self.rejections.values().sum::<u64>();
+        rejected as f64 / self.rows as f64
+    }
+
+    /// Writes the summary as a report, along with the net flow of funds into client accounts
+    /// according to the control totals.
+    pub fn write<W: Write>(&self, totals: &Reconciliation, mut out: W) -> io::Result<()> {
+        writeln!(out, "rows: {}", self.rows)?;
+        for (tx_type, count) in &self.by_type {
+            writeln!(out, "  {}: {count}", format!("{tx_type:?}").to_lowercase())?;
+        }
+        writeln!(out, "unreadable rows: {}", self.unreadable)?;
+
+        // Most common reasons first.
+        let mut rejections: Vec<_> = self.rejections.iter().collect();
+        rejections
+            .sort_by_key(|(rejection, count)| (std::cmp::Reverse(**count), rejection.to_string()));
+        writeln!(out, "rejected: {}", self.rejections.values().sum::<u64>())?;
+        for (rejection, count) in rejections {
+            writeln!(out, "  {rejection}: {count}")?;
+        }
+        writeln!(out, "rejection rate: {:.2}%", self.rejection_rate() * 100.0)?;
+        writeln!(out, "clients: {}", self.clients.len())?;
+
+        writeln!(out, "deposited: {}", format_amount(totals.deposits))?;
+        writeln!(out, "withdrawn: {}", format_amount(totals.withdrawals))?;
+        writeln!(out, "charged back: {}", format_amount(totals.chargebacks))?;
+        writeln!(
+            out,
+            "net flow: {}",
+            format_amount(totals.deposits - totals.withdrawals - totals.chargebacks)
+        )
+    }
+}
+
+/// Formats a signed fixed-precision amount like `Amount`.
+pub fn format_amount(amount: i128) -> String {
+    let sign = if amount < 0 { "-" } else { "" };
+    let amount = amount.unsigned_abs();
+    let fraction = format!("{:04}", amount % 10_000);
+    let fraction = fraction.trim_end_matches('0');
+    let fraction = if fraction.is_empty() { "0" } else { fraction };
+    format!("{sign}{}.{fraction}", amount / 10_000)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::summary::format_amount;
+
+    #[test]
+    fn signed_amounts() {
+        assert_eq!(format_amount(15_000), "1.5");
+        assert_eq!(format_amount(-10_000), "-1.0");
+        assert_eq!(format_amount(0), "0.0");
+    }
+}
@@ -1,8 +1,12 @@
+use std::fmt;
 use std::ops::{Add, AddAssign, Div, Rem, SubAssign};
+use std::str::FromStr;
 
-use serde::{Deserialize, Serialize};
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
+use tracing::warn;
 
-#[derive(Debug, Deserialize, Clone, Copy, Serialize)]
+#[derive(Debug, Deserialize, Clone, Serialize)]
 pub struct Transaction {
     #[serde(rename = "type")]
     pub tx_type: TxType,
@@ -10,9 +14,14 @@ pub struct Transaction {
     #[serde(rename = "tx")]
     pub id: u32,
     pub amount: Option<Amount>,
+    // Admin actions (lock, unlock and close) must say who performed them, and why.
+    pub operator: Option<u32>,
+    pub reason: Option<String>,
+    // Seconds since the Unix epoch. Timestamps are optional, but must be non-decreasing when given.
+    pub timestamp: Option<u64>,
 }
 
-#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Serialize)]
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
 pub enum TxType {
     #[serde(rename = "deposit")]
     Deposit,
@@ -24,15 +33,25 @@ pub enum TxType {
     Resolve,
     #[serde(rename = "chargeback")]
     Chargeback,
+    #[serde(rename = "lock")]
+    Lock,
+    #[serde(rename = "unlock")]
+    Unlock,
+    #[serde(rename = "close")]
+    Close,
 }
 
 // Amounts in the input file are fixed-precision (4 decimal places), so using a float can cause
 // inaccuracies in edge cases. We will use a custom fixed-precision datatype instead.
-#[derive(PartialEq, Debug, Clone, Copy, Deserialize, Serialize, Default, Eq, PartialOrd, Ord)]
-#[serde(from = "f64", into = "f64")]
+#[derive(PartialEq, Debug, Clone, Copy, Serialize, Default, Eq, PartialOrd, Ord)]
+#[serde(into = "f64")]
 pub struct Amount(pub u64);
 
 impl Amount {
+    pub fn checked_add(self, rhs: Amount) -> Option<Amount> {
+        self.0.checked_add(rhs.0).map(Amount)
+    }
+
     pub fn checked_sub(self, rhs: Amount) -> Option<Amount> {
         self.0.checked_sub(rhs.0).map(Amount)
     }
@@ -42,21 +61,107 @@ impl Amount {
     }
 }
 
-// Convert from float to fixed precision Amount. Rounds float down to 4 decimal places.
+// Parse a decimal amount exactly. Like floats, digits beyond 4 decimal places are rounded down.
+impl FromStr for Amount {
+    type Err = String;
+
+    fn from_str(decimal: &str) -> Result<Amount, String> {
+        let (whole, fraction) = decimal.split_once('.').unwrap_or((decimal, ""));
+        let is_digits = |digits: &str| digits.bytes().all(|digit| digit.is_ascii_digit());
+        if (whole.is_empty() && fraction.is_empty()) || !is_digits(whole) || !is_digits(fraction) {
+            return Err(format!("{decimal} is not a non-negative decimal amount"));
+        }
+
+        let whole: u64 = if whole.is_empty() {
+            0
+        } else {
+            whole.parse().map_err(|e| format!("{decimal}: {e}"))?
+        };
+        let fraction = fraction
+            .bytes()
+            .chain([b'0'; 4])
+            .take(4)
+            .fold(0, |fraction, digit| fraction * 10 + u64::from(digit - b'0'));
+        whole
+            .checked_mul(10_000)
+            .and_then(|whole| whole.checked_add(fraction))
+            .map(Amount)
+            .ok_or_else(|| format!("{decimal} is too large"))
+    }
+}
+
+// Format as a decimal, with trailing zeros removed but at least one decimal place.
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let fraction = format!("{:04}", self.0 % 10_000);
+        let fraction = fraction.trim_end_matches('0');
+        let fraction = if fraction.is_empty() { "0" } else { fraction };
+        write!(f, "{}.{fraction}", self.0 / 10_000)
+    }
+}
+
+// Convert from float to fixed precision Amount. Rounds float down to 4 decimal places. The float is
+// converted via its shortest decimal representation, so that e.g. 0.3 isn't rounded down to 0.2999.
 impl From<f64> for Amount {
     fn from(float: f64) -> Amount {
-        if float < 0.0 || float > u64::MAX as f64 / 10_000.0 {
-            eprintln!("Transaction amount is negative or too large; replacing amount with 0");
+        if float < 0.0 || !float.is_finite() {
+            warn!("Transaction amount is negative or too large; replacing amount with 0");
             return Amount(0);
         }
-        Amount((float * 10_000.0).floor() as u64)
+        float.to_string().parse().unwrap_or_else(|_| {
+            warn!("Transaction amount is negative or too large; replacing amount with 0");
+            Amount(0)
+        })
     }
 }
 
-// Convert from fixed precision amount to f64.
+// Convert from fixed precision amount to the nearest f64.
 impl From<Amount> for f64 {
     fn from(amount: Amount) -> f64 {
-        (amount.0 / 10_000) as f64 + (amount.0 % 10_000) as f64 / 10_000.0
+        amount
+            .to_string()
+            .parse()
+            .expect("formatted amount is a valid float")
+    }
+}
+
+// Amounts may be given as numbers, or as decimal strings if they must be read exactly. The csv
+// deserializer offers numeric fields as floats, so csv input parses amounts again from their text.
+impl<'de> Deserialize<'de> for Amount {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Amount, D::Error> {
+        deserializer.deserialize_any(AmountVisitor)
+    }
+}
+
+struct AmountVisitor;
+
+impl Visitor<'_> for AmountVisitor {
+    type Value = Amount;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a non-negative decimal amount")
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<Amount, E> {
+        value
+            .checked_mul(10_000)
+            .map(Amount)
+            .ok_or_else(|| E::custom(format!("{value} is too large")))
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<Amount, E> {
+        match u64::try_from(value) {
+            Ok(value) => self.visit_u64(value),
+            Err(_) => Ok(Amount::from(value as f64)),
+        }
+    }
+
+    fn visit_f64<E: de::Error>(self, value: f64) -> Result<Amount, E> {
+        Ok(Amount::from(value))
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Amount, E> {
+        value.parse().map_err(E::custom)
     }
 }
 
@@ -84,19 +189,21 @@ impl Div<u64> for Amount {
     }
 }
 
-// Allow addition between Amount and Amount.
+// Allow addition between Amount and Amount. Sums saturate rather than overflow: the engine rejects
+// transactions that would take a balance past the largest amount, so only sums that are compared
+// against a limit, such as the amount disputed or withdrawn, can reach it.
 impl Add<Amount> for Amount {
     type Output = Amount;
 
     fn add(self, rhs: Amount) -> Self {
-        Amount(self.0 + rhs.0)
+        Amount(self.0.saturating_add(rhs.0))
     }
 }
 
-// Allow += operation between Amount and Amount.
+// Allow += operation between Amount and Amount, saturating like addition.
 impl AddAssign for Amount {
     fn add_assign(&mut self, other: Self) {
-        *self = Self(self.0 + other.0);
+        *self = Self(self.0.saturating_add(other.0));
     }
 }
 
@@ -107,16 +214,50 @@ impl SubAssign for Amount {
     }
 }
 
+/// Creates a transaction without a timestamp or admin details, for tests.
+#[cfg(test)]
+pub fn transaction(tx_type: TxType, client: u16, id: u32, amount: Option<u64>) -> Transaction {
+    Transaction {
+        tx_type,
+        client,
+        id,
+        amount: amount.map(Amount),
+        operator: None,
+        reason: None,
+        timestamp: None,
+    }
+}
+
 #[cfg(test)]
 mod test {
     use crate::transaction::Amount;
 
     #[test]
+    #[allow(clippy::unreadable_literal)]
     fn amount_from_float() {
         assert_eq!(Amount::from(123_456.78912345), Amount(1_234_567_891));
     }
 
     #[test]
+    fn amount_from_imprecise_float() {
+        assert_eq!(Amount::from(0.3), Amount(3_000));
+    }
+
+    #[test]
+    fn amount_from_str() {
+        assert_eq!("123456.78912345".parse(), Ok(Amount(1_234_567_891)));
+        assert_eq!(".5".parse(), Ok(Amount(5_000)));
+        assert_eq!("7".parse(), Ok(Amount(70_000)));
+        assert!("-1".parse::<Amount>().is_err());
+        assert!("1e5".parse::<Amount>().is_err());
+    }
+
+    #[test]
+    #[allow(
+        clippy::float_cmp,
+        clippy::inconsistent_digit_grouping,
+        clippy::semicolon_if_nothing_returned
+    )]
     fn float_from_amount() {
         let amount = Amount(1_234_567_891);
         assert_eq!(f64::from(amount), 123_456.7891)